@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+use quadtree::{PointIndex, Quadtree, Rect, Vector2f};
+use rand_distr::{Distribution, Normal};
+
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 600.0;
+const CAPACITY: usize = 8;
+const ROUNDS: u32 = 20;
+
+fn points(count: usize) -> Vec<PointIndex<f32>> {
+    let normal_w = Normal::new(WIDTH / 2.0, WIDTH / 8.0).unwrap();
+    let normal_h = Normal::new(HEIGHT / 2.0, HEIGHT / 8.0).unwrap();
+    let random = &mut rand::thread_rng();
+
+    (0..count)
+        .map(|idx| PointIndex {
+            position: Vector2f::new(normal_w.sample(random), normal_h.sample(random)),
+            data: Some(idx),
+        })
+        .collect()
+}
+
+fn time<F: FnMut() -> usize>(mut build: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        assert!(build() > 0);
+    }
+
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let bounds = Rect::new(0.0, 0.0, WIDTH, HEIGHT);
+
+    for &count in &[500, 10_000, 100_000] {
+        let points = points(count);
+
+        let insert = time(|| {
+            let mut tree = Quadtree::new(bounds, CAPACITY);
+            for point in points.iter() {
+                let _ = tree.insert(*point);
+            }
+            tree.len()
+        });
+        let bulk = time(|| Quadtree::from_points(bounds, CAPACITY, points.iter().copied()).len());
+
+        println!(
+            "{:>7} points: insert {:>10.3?}  from_points {:>10.3?}  ({:.2}x)",
+            count,
+            insert,
+            bulk,
+            insert.as_secs_f64() / bulk.as_secs_f64()
+        );
+    }
+}
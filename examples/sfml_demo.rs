@@ -3,7 +3,7 @@ use sfml::graphics::{
     Transformable,
 };
 
-use quadtree::{self, PointIndex, Quadtree};
+use quadtree::{self, NodeRef, PointIndex, Quadtree};
 
 //----- Settings ------//
 const WIN_W: f32 = 800.0;
@@ -16,17 +16,15 @@ trait Drawable {
 
 impl Drawable for Quadtree<f32> {
     fn draw(&self, window: &mut RenderWindow) {
-        if self.quads.is_none() && self.capacity <= 0 {
-            return;
-        }
-
-        if self.quads.is_some() {
-            for quad in self.quads.as_ref().unwrap().iter() {
-                quad.draw(window);
-            }
+        for node in self.nodes() {
+            node.draw(window);
         }
+    }
+}
 
-        if self.children.len() > 0 {
+impl Drawable for NodeRef<'_, f32> {
+    fn draw(&self, window: &mut RenderWindow) {
+        if !self.is_empty() {
             let random = &mut rand::thread_rng();
 
             use rand::Rng;
@@ -37,9 +35,9 @@ impl Drawable for Quadtree<f32> {
             );
 
             let mut my_rect = RectangleShape::with_size(
-                (self.bounds.width - 2.0, self.bounds.height - 2.0).into(),
+                (self.bounds().width - 2.0, self.bounds().height - 2.0).into(),
             );
-            my_rect.set_position((self.bounds.left + 1.0, self.bounds.top + 1.0));
+            my_rect.set_position((self.bounds().left + 1.0, self.bounds().top + 1.0));
             my_rect.set_fill_color(Color::TRANSPARENT);
             my_rect.set_outline_color(my_color);
             my_rect.set_outline_thickness(1.0);
@@ -48,8 +46,8 @@ impl Drawable for Quadtree<f32> {
             let mut my_dot = CircleShape::new(2.0, 8);
             my_dot.set_fill_color(my_color);
 
-            for child in &self.children {
-                my_dot.set_position((child.position.x.into(), child.position.y.into()));
+            for child in self.points() {
+                my_dot.set_position((child.position.x, child.position.y));
                 window.draw_circle_shape(&my_dot, RenderStates::default());
             }
         }
@@ -77,11 +75,9 @@ fn main() {
             match event {
                 Event::Closed => window.close(),
                 Event::MouseButtonPressed { .. } => {}
-                Event::KeyPressed { code, .. } => {
-                    if code == Key::Escape {
-                        window.close();
-                    }
-                }
+                Event::KeyPressed {
+                    code: Key::Escape, ..
+                } => window.close(),
                 _ => {}
             }
         }
@@ -92,22 +88,20 @@ fn main() {
 
             // Random points for testing.
             use rand_distr::{Distribution, Normal};
-            let normal_w = Normal::new(WIN_W as f32 / 2.0, WIN_W as f32 / 8.0).unwrap();
-            let normal_h = Normal::new(WIN_H as f32 / 2.0, WIN_H as f32 / 8.0).unwrap();
+            let normal_w = Normal::new(WIN_W / 2.0, WIN_W / 8.0).unwrap();
+            let normal_h = Normal::new(WIN_H / 2.0, WIN_H / 8.0).unwrap();
             let random = &mut rand::thread_rng();
             let mut vectors = Vec::new();
             for _idx in 0..500 {
                 let rng_x = normal_w.sample(random) as f32;
                 let rng_y = normal_h.sample(random) as f32;
-                let my_vector = PointIndex {
-                    position: quadtree::Vector2f::new(rng_x, rng_y),
-                    index: None,
-                };
+                let my_vector = PointIndex::with_index(quadtree::Vector2f::new(rng_x, rng_y), None);
                 vectors.push(my_vector);
             }
 
             for vector in vectors {
-                quad_root.insert(vector);
+                // Samples outside of the window are simply dropped.
+                let _ = quad_root.insert(vector);
             }
         }
 
@@ -124,6 +118,6 @@ fn main() {
         }
 
         // std::thread::yield_now();
-        std::thread::sleep(std::time::Duration::from_millis(1000 / 1));
+        std::thread::sleep(std::time::Duration::from_secs(1));
     }
 }
@@ -0,0 +1,337 @@
+use crate::Zero;
+use std::ops::{Add, Mul, Sub};
+
+/// Number type positions and bounds are made of.
+///
+/// Implemented for the primitive floats and integers. Fixed-point types only need to provide
+/// exact halving and overflow checks on top of their arithmetic, the trees never round through a
+/// float. Distance, ray and polygon queries still ask for `Float`.
+pub trait Coordinate:
+    Copy + PartialOrd + Zero + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    /// Half of `self`, rounded towards zero for integers.
+    ///
+    /// Splits give the lower part this much and the upper part whatever is left, so integer
+    /// bounds of odd size are still covered exactly.
+    fn half(self) -> Self;
+
+    /// `None` when the sum overflows, or for floats is no longer finite.
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    /// `None` when the difference overflows, or for floats is no longer finite.
+    fn checked_sub(self, other: Self) -> Option<Self>;
+}
+
+macro_rules! float_coordinate {
+    ($($ty:ty),*) => {
+        $(
+            impl Coordinate for $ty {
+                fn half(self) -> Self {
+                    self / 2.0
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    Some(self + other).filter(|sum| sum.is_finite())
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    Some(self - other).filter(|difference| difference.is_finite())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! integer_coordinate {
+    ($($ty:ty),*) => {
+        $(
+            impl Coordinate for $ty {
+                fn half(self) -> Self {
+                    self / 2
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_add(self, other)
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_sub(self, other)
+                }
+            }
+        )*
+    };
+}
+
+float_coordinate!(f32, f64);
+integer_coordinate!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+// `abs` for any coordinate, negating through zero so unsigned types never need it.
+pub(crate) fn abs<T: Coordinate>(value: T) -> T {
+    if value < T::zero() {
+        T::zero() - value
+    } else {
+        value
+    }
+}
+
+// Distance between two coordinates, always taking the smaller from the larger so unsigned types
+// never wrap.
+pub(crate) fn difference<T: Coordinate>(a: T, b: T) -> T {
+    if a < b {
+        b - a
+    } else {
+        a - b
+    }
+}
+
+// Distance between the ranges `a` and `b` along one axis, zero where they overlap or touch.
+pub(crate) fn gap<T: Coordinate>((a_min, a_max): (T, T), (b_min, b_max): (T, T)) -> T {
+    if b_min > a_max {
+        b_min - a_max
+    } else if a_min > b_max {
+        a_min - b_max
+    } else {
+        T::zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Aabb, Aabb3, BinaryTree, Entry, Entry3, Interval, Item, Octree, Quadtree, Rect, RectEntry,
+        RectQuadtree, Vector2, Vector3,
+    };
+    use std::fmt::Debug;
+
+    // 16.16 fixed point, standing in for the fixed-point types users bring along.
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    struct Fixed(i32);
+
+    impl Fixed {
+        fn new(value: f64) -> Self {
+            Fixed((value * 65536.0) as i32)
+        }
+    }
+
+    impl Add for Fixed {
+        type Output = Fixed;
+
+        fn add(self, other: Fixed) -> Fixed {
+            Fixed(self.0 + other.0)
+        }
+    }
+
+    impl Sub for Fixed {
+        type Output = Fixed;
+
+        fn sub(self, other: Fixed) -> Fixed {
+            Fixed(self.0 - other.0)
+        }
+    }
+
+    impl Mul for Fixed {
+        type Output = Fixed;
+
+        fn mul(self, other: Fixed) -> Fixed {
+            Fixed(((i64::from(self.0) * i64::from(other.0)) >> 16) as i32)
+        }
+    }
+
+    impl Zero for Fixed {
+        fn zero() -> Fixed {
+            Fixed(0)
+        }
+
+        fn is_zero(&self) -> bool {
+            self.0 == 0
+        }
+    }
+
+    impl Coordinate for Fixed {
+        fn half(self) -> Fixed {
+            Fixed(self.0 / 2)
+        }
+
+        fn checked_add(self, other: Fixed) -> Option<Fixed> {
+            self.0.checked_add(other.0).map(Fixed)
+        }
+
+        fn checked_sub(self, other: Fixed) -> Option<Fixed> {
+            self.0.checked_sub(other.0).map(Fixed)
+        }
+    }
+
+    // Inserts every cell of a `width` by `height` grid at `origin`, then finds each one again.
+    fn fill_rect<T: Coordinate + From<u8> + Debug>(origin: T, width: u8, height: u8) {
+        let cell = |x: u8, y: u8| Vector2::new(origin + T::from(x), origin + T::from(y));
+        let cells: Vec<_> = (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .collect();
+        let bounds = Rect::new(origin, origin, T::from(width), T::from(height));
+
+        let mut tree: Quadtree<T, usize> = Quadtree::new(bounds, 1);
+        for (idx, &(x, y)) in cells.iter().enumerate() {
+            tree.insert(Entry::new(cell(x, y), idx)).unwrap();
+        }
+
+        assert_eq!(tree.len(), cells.len());
+        assert_eq!(tree.query(bounds).len(), cells.len());
+        for (idx, &(x, y)) in cells.iter().enumerate() {
+            let position = cell(x, y);
+            let found = tree.query(Rect::new(position.x, position.y, T::from(1), T::from(1)));
+            assert_eq!(found.len(), 1, "{:?} in {:?}", position, bounds);
+            assert_eq!(found[0].data, idx);
+        }
+    }
+
+    fn fill_aabb3<T: Coordinate + From<u8> + Debug>(origin: T, size: u8) {
+        let cell = |x: u8, y: u8, z: u8| {
+            Vector3::new(
+                origin + T::from(x),
+                origin + T::from(y),
+                origin + T::from(z),
+            )
+        };
+        let cells: Vec<_> = (0..size)
+            .flat_map(|x| (0..size).flat_map(move |y| (0..size).map(move |z| (x, y, z))))
+            .collect();
+        let side = T::from(size);
+        let bounds = Aabb3::new(cell(0, 0, 0), Vector3::new(side, side, side));
+
+        let mut tree: Octree<T, usize> = Octree::new(bounds, 1);
+        for (idx, &(x, y, z)) in cells.iter().enumerate() {
+            tree.insert(Entry3::new(cell(x, y, z), idx)).unwrap();
+        }
+
+        assert_eq!(tree.len(), cells.len());
+        assert_eq!(tree.query(bounds).len(), cells.len());
+        for (idx, &(x, y, z)) in cells.iter().enumerate() {
+            let one = Vector3::new(T::from(1), T::from(1), T::from(1));
+            let found = tree.query(Aabb3::new(cell(x, y, z), one));
+            assert_eq!(found.len(), 1, "{:?} in {:?}", cell(x, y, z), bounds);
+            assert_eq!(found[0].data, idx);
+        }
+    }
+
+    fn fill_interval<T: Coordinate + From<u8> + Debug>(origin: T, size: u8) {
+        let bounds = Interval::new([origin], [T::from(size)]);
+
+        let mut tree: BinaryTree<T, u8> = BinaryTree::new(bounds, 1);
+        for x in 0..size {
+            tree.insert(Item::new([origin + T::from(x)], x)).unwrap();
+        }
+
+        assert_eq!(tree.len(), usize::from(size));
+        assert_eq!(tree.query(bounds).len(), usize::from(size));
+        for x in 0..size {
+            let found = tree.query(Aabb::new([origin + T::from(x)], [T::from(1)]));
+            assert_eq!(found.len(), 1, "{} in {:?}", x, bounds);
+            assert_eq!(found[0].data, x);
+        }
+    }
+
+    #[test]
+    fn integer_rects_cover_every_cell() {
+        for width in 1..14 {
+            for height in 1..14 {
+                fill_rect(-7i32, width, height);
+                fill_rect(3u8, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_boxes_cover_every_cell() {
+        for size in 1..8 {
+            fill_aabb3(-5i32, size);
+            fill_aabb3(2u8, size);
+        }
+    }
+
+    #[test]
+    fn integer_intervals_cover_every_cell() {
+        for size in 1..100 {
+            fill_interval(-50i32, size);
+            fill_interval(7u8, size);
+        }
+    }
+
+    #[test]
+    fn unsigned_distances_do_not_wrap() {
+        let mut tree: Quadtree<u32, u8> = Quadtree::new(Rect::new(0, 0, 100, 100), 1);
+        for (idx, &(x, y)) in [(0, 0), (3, 4), (60, 60), (90, 5)].iter().enumerate() {
+            tree.insert(Entry::new(Vector2::new(x, y), idx as u8))
+                .unwrap();
+        }
+
+        assert_eq!(
+            Vector2::new(3u32, 4).distance_squared(Vector2::new(0, 0)),
+            25
+        );
+        assert_eq!(
+            Vector3::new(0u32, 0, 2).distance_squared(Vector3::new(3, 4, 2)),
+            25
+        );
+
+        let mut near: Vec<_> = tree
+            .query_radius(Vector2::new(1, 1), 5)
+            .iter()
+            .map(|entry| entry.data)
+            .collect();
+        near.sort_unstable();
+        assert_eq!(near, [0, 1]);
+
+        let pairs = tree.collision_pairs(5);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0.data + pairs[0].1.data, 1);
+    }
+
+    #[test]
+    fn unsigned_rect_quadtree() {
+        let mut tree: RectQuadtree<u16, u8> = RectQuadtree::new(Rect::new(0, 0, 64, 64), 1);
+        for idx in 0..8u8 {
+            let at = u16::from(idx) * 8;
+            tree.insert(RectEntry::new(Rect::new(at, at, 3, 3), idx))
+                .unwrap();
+        }
+
+        assert_eq!(tree.len(), 8);
+        assert_eq!(tree.query(Rect::new(0, 0, 20, 20)).len(), 3);
+        assert!(tree.collision_pairs().is_empty());
+    }
+
+    #[test]
+    fn fixed_point_tree() {
+        let bounds = Rect::new(
+            Fixed::new(0.0),
+            Fixed::new(0.0),
+            Fixed::new(10.0),
+            Fixed::new(10.0),
+        );
+        let mut tree: Quadtree<Fixed, usize> = Quadtree::new(bounds, 2).set_auto_expand(true);
+        for idx in 0..500 {
+            let value = idx as f64 * 0.05;
+            let position = Vector2::new(Fixed::new(value), Fixed::new(value * 0.5));
+            tree.insert(Entry::new(position, idx)).unwrap();
+        }
+
+        assert_eq!(tree.len(), 500);
+        assert!(tree.bounds().width > Fixed::new(20.0));
+
+        let half = Rect::new(
+            Fixed::new(0.0),
+            Fixed::new(0.0),
+            Fixed::new(5.0),
+            Fixed::new(5.0),
+        );
+        assert_eq!(tree.query(half).len(), 100);
+
+        let origin = Vector2::new(Fixed::new(0.0), Fixed::new(0.0));
+        assert_eq!(tree.query_radius(origin, Fixed::new(1.0)).len(), 18);
+
+        // Doubling the root past 32768 overflows the 16 integer bits.
+        let far = Vector2::new(Fixed::new(30000.0), Fixed::new(0.0));
+        assert!(tree.insert(Entry::new(far, 0)).is_err());
+    }
+}
@@ -1,7 +1,39 @@
 pub use num_traits::float::Float;
+pub use num_traits::Zero;
 pub use std::ops::{Add, Div, Sub};
 
+mod coordinate;
+mod octree;
+mod orthtree;
+mod quadtree;
+mod region;
+mod snapshot;
+mod tree;
+
+#[cfg(test)]
+mod test_support;
+
+pub use coordinate::Coordinate;
+pub use octree::{Aabb3, Entry3, Octree, PointIndex3, Vector3, Vector3f};
+pub use orthtree::{Aabb, BinaryTree, Interval, Item, Orthtree};
+pub use quadtree::{
+    IntoIter, Iter, IterMut, Leaves, Neighbor, NodePoints, NodeRef, Nodes, Quadtree, Query, RayHit,
+    Visit, Visitor,
+};
+pub use region::{RectEntry, RectIndex, RectPair, RectQuadtree, RectQuery};
+pub use snapshot::{Encode, SnapshotError};
+pub use tree::{Bounds, Positioned, SpatialTree};
+
+use coordinate::{abs, difference, gap};
+use std::error::Error;
+use std::fmt;
+use std::ops::{Mul, Neg};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Vector2<T> {
     pub x: T,
     pub y: T,
@@ -13,9 +45,113 @@ impl<T> Vector2<T> {
     }
 }
 
+impl<T: Coordinate> Vector2<T> {
+    pub fn dot(self, other: Vector2<T>) -> T {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length_squared(self) -> T {
+        self.dot(self)
+    }
+
+    pub fn distance_squared(self, other: Vector2<T>) -> T {
+        Vector2::new(difference(self.x, other.x), difference(self.y, other.y)).length_squared()
+    }
+}
+
+impl<T: Float + Coordinate> Vector2<T> {
+    pub fn length(self) -> T {
+        self.length_squared().sqrt()
+    }
+
+    pub fn distance(self, other: Vector2<T>) -> T {
+        self.distance_squared(other).sqrt()
+    }
+
+    /// Scales to a length of one, a zero vector is returned as is.
+    pub fn normalize(self) -> Self {
+        let length = self.length();
+        if length == T::zero() {
+            return self;
+        }
+
+        self / length
+    }
+
+    /// Linear interpolation, `t` of zero gives `self` and one gives `other`.
+    pub fn lerp(self, other: Vector2<T>, t: T) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl<T: Add<Output = T>> Add for Vector2<T> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Vector2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Vector2<T> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Vector2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl<T: Mul<Output = T> + Copy> Mul<T> for Vector2<T> {
+    type Output = Self;
+
+    fn mul(self, scalar: T) -> Self {
+        Vector2::new(self.x * scalar, self.y * scalar)
+    }
+}
+
+impl<T: Div<Output = T> + Copy> Div<T> for Vector2<T> {
+    type Output = Self;
+
+    fn div(self, scalar: T) -> Self {
+        Vector2::new(self.x / scalar, self.y / scalar)
+    }
+}
+
+impl<T: Neg<Output = T>> Neg for Vector2<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Vector2::new(-self.x, -self.y)
+    }
+}
+
+impl<T> From<(T, T)> for Vector2<T> {
+    fn from((x, y): (T, T)) -> Self {
+        Vector2 { x, y }
+    }
+}
+
+impl<T> From<[T; 2]> for Vector2<T> {
+    fn from([x, y]: [T; 2]) -> Self {
+        Vector2 { x, y }
+    }
+}
+
+impl<T> From<Vector2<T>> for (T, T) {
+    fn from(vector: Vector2<T>) -> Self {
+        (vector.x, vector.y)
+    }
+}
+
+impl<T> From<Vector2<T>> for [T; 2] {
+    fn from(vector: Vector2<T>) -> Self {
+        [vector.x, vector.y]
+    }
+}
+
 pub type Vector2f = Vector2<f32>;
 
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Rect<T> {
     pub left: T,
     pub top: T,
@@ -23,7 +159,7 @@ pub struct Rect<T> {
     pub height: T,
 }
 
-impl<T: Float + PartialOrd + Add<Output = T> + Sub<Output = T> + Copy> Rect<T> {
+impl<T: Coordinate> Rect<T> {
     pub fn new(left: T, top: T, width: T, height: T) -> Self {
         Rect {
             left,
@@ -42,51 +178,349 @@ impl<T: Float + PartialOrd + Add<Output = T> + Sub<Output = T> + Copy> Rect<T> {
         }
     }
 
+    pub fn right(self) -> T {
+        self.left + self.width
+    }
+
+    pub fn bottom(self) -> T {
+        self.top + self.height
+    }
+
+    pub fn center(self) -> Vector2<T> {
+        Vector2::new(self.left + self.width.half(), self.top + self.height.half())
+    }
+
+    pub fn area(self) -> T {
+        abs(self.width * self.height)
+    }
+
+    /// Flips a negative width or height around so `left` and `top` are the smallest coordinates.
+    pub fn normalize(self) -> Self {
+        Rect::new(
+            min(self.left, self.right()),
+            min(self.top, self.bottom()),
+            abs(self.width),
+            abs(self.height),
+        )
+    }
+
+    /// Half-open, positions on the right and bottom edges are outside.
     pub fn contains(self, position: Vector2<T>) -> bool {
-        let x = position.x;
-        let y = position.y;
-        let (min_x, max_x) = (
-            min(self.left, self.left + self.width),
-            max(self.left, self.left + self.width),
-        );
-        let (min_y, max_y) = (
-            min(self.top, self.top + self.height),
-            max(self.top, self.top + self.height),
-        );
+        let rect = self.normalize();
+
+        position.x >= rect.left
+            && position.x < rect.right()
+            && position.y >= rect.top
+            && position.y < rect.bottom()
+    }
 
-        // <= ?
-        x >= min_x && x < max_x && y >= min_y && y < max_y
+    pub fn contains_rect(self, rect: Rect<T>) -> bool {
+        let (outer, inner) = (self.normalize(), rect.normalize());
+
+        inner.left >= outer.left
+            && inner.right() <= outer.right()
+            && inner.top >= outer.top
+            && inner.bottom() <= outer.bottom()
     }
 
+    /// Whether the two share any point at all, merely touching edges included.
+    pub fn intersects(self, rect: Rect<T>) -> bool {
+        let (a, b) = (self.normalize(), rect.normalize());
+
+        a.left <= b.right() && b.left <= a.right() && a.top <= b.bottom() && b.top <= a.bottom()
+    }
+
+    /// The area shared by the two, `None` unless it is larger than zero.
     pub fn overlap(self, rect: Rect<T>) -> Option<Rect<T>> {
-        let (s_min_x, s_max_x) = (
-            min(self.left, self.left + self.width),
-            max(self.left, self.left + self.width),
-        );
-        let (s_min_y, s_max_y) = (
-            min(self.top, self.top + self.height),
-            max(self.top, self.top + self.height),
-        );
-        let (r_min_x, r_max_x) = (
-            min(rect.left, rect.left + rect.width),
-            max(rect.left, rect.left + rect.width),
-        );
-        let (r_min_y, r_max_y) = (
-            min(rect.top, rect.top + rect.height),
-            max(rect.top, rect.top + rect.height),
-        );
+        let (a, b) = (self.normalize(), rect.normalize());
 
-        let left = max(s_min_x, r_min_x);
-        let top = max(s_min_y, r_min_y);
-        let right = min(s_max_x, r_max_x);
-        let bottom = min(s_max_y, r_max_y);
+        let left = max(a.left, b.left);
+        let top = max(a.top, b.top);
+        let right = min(a.right(), b.right());
+        let bottom = min(a.bottom(), b.bottom());
 
         if left < right && top < bottom {
-            return Some(Rect::new(left, top, right - left, top - bottom));
+            return Some(Rect::new(left, top, right - left, bottom - top));
         }
 
         None
     }
+
+    /// The smallest rect covering both.
+    pub fn union(self, rect: Rect<T>) -> Rect<T> {
+        let (a, b) = (self.normalize(), rect.normalize());
+
+        let left = min(a.left, b.left);
+        let top = min(a.top, b.top);
+        let right = max(a.right(), b.right());
+        let bottom = max(a.bottom(), b.bottom());
+
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// Moves every edge outwards by `amount`, or inwards for negative amounts, collapsing onto
+    /// the center rather than turning inside out.
+    pub fn expand(self, amount: T) -> Rect<T> {
+        let rect = self.normalize();
+        let center = rect.center();
+
+        let width = rect.width + amount + amount;
+        let height = rect.height + amount + amount;
+        let (left, width) = if width < T::zero() {
+            (center.x, T::zero())
+        } else {
+            (rect.left - amount, width)
+        };
+        let (top, height) = if height < T::zero() {
+            (center.y, T::zero())
+        } else {
+            (rect.top - amount, height)
+        };
+
+        Rect::new(left, top, width, height)
+    }
+
+    /// Splits into the NW, NE, SE and SW quarters, in that order. The east and south quarters
+    /// take the odd unit when integer sizes do not halve evenly.
+    pub fn quarters(self) -> [Rect<T>; 4] {
+        let (x, y) = (self.left, self.top);
+        let (w, h) = (self.width.half(), self.height.half());
+        let (east, south) = (self.width - w, self.height - h);
+
+        [
+            Rect::new(x, y, w, h),
+            Rect::new(x + w, y, east, h),
+            Rect::new(x + w, y + h, east, south),
+            Rect::new(x, y + h, w, south),
+        ]
+    }
+}
+
+impl<T: Float + Coordinate> Rect<T> {
+    pub fn intersects_circle(self, circle: Circle<T>) -> bool {
+        circle.intersects(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Circle<T> {
+    pub center: Vector2<T>,
+    pub radius: T,
+}
+
+impl<T: Coordinate> Circle<T> {
+    pub fn new(center: Vector2<T>, radius: T) -> Self {
+        Circle { center, radius }
+    }
+
+    pub fn contains(self, position: Vector2<T>) -> bool {
+        self.center.distance_squared(position) <= self.radius * self.radius
+    }
+
+    pub fn intersects(self, rect: Rect<T>) -> bool {
+        rect_distance_squared(rect, self.center) <= self.radius * self.radius
+    }
+}
+
+/// Convex polygon, such as a rotated camera view. Its vertices are kept in clockwise order on
+/// screen (y pointing down) whichever way they were given.
+///
+/// Polygons with fewer than three vertices contain nothing.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConvexPolygon<T> {
+    vertices: Vec<Vector2<T>>,
+}
+
+impl<T: Float + Coordinate> ConvexPolygon<T> {
+    /// Expects `vertices` to already describe a convex polygon, in either winding order.
+    pub fn new(mut vertices: Vec<Vector2<T>>) -> Self {
+        if signed_area(&vertices) < T::zero() {
+            vertices.reverse();
+        }
+
+        ConvexPolygon { vertices }
+    }
+
+    pub fn from_rect(rect: Rect<T>) -> Self {
+        ConvexPolygon::new(corners(rect.normalize()).to_vec())
+    }
+
+    pub fn vertices(&self) -> &[Vector2<T>] {
+        &self.vertices
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Vector2<T>, Vector2<T>)> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    /// Smallest `Rect` around the polygon.
+    pub fn bounds(&self) -> Rect<T> {
+        let first = self
+            .vertices
+            .first()
+            .copied()
+            .unwrap_or(Vector2::new(T::zero(), T::zero()));
+
+        self.vertices.iter().fold(
+            Rect::new(first.x, first.y, T::zero(), T::zero()),
+            |bounds, &vertex| bounds.union(Rect::new(vertex.x, vertex.y, T::zero(), T::zero())),
+        )
+    }
+
+    /// Inclusive of the edges.
+    pub fn contains(&self, position: Vector2<T>) -> bool {
+        self.vertices.len() >= 3
+            && self
+                .edges()
+                .all(|(a, b)| cross(b - a, position - a) >= T::zero())
+    }
+
+    /// Separating axis test, touching counts as intersecting.
+    pub fn intersects(&self, rect: Rect<T>) -> bool {
+        if self.vertices.len() < 3 {
+            return false;
+        }
+
+        let rect = rect.normalize();
+        let corners = corners(rect);
+        let separated = |axis: Vector2<T>| {
+            let (a_min, a_max) = project(&self.vertices, axis);
+            let (b_min, b_max) = project(&corners, axis);
+
+            a_max < b_min || b_max < a_min
+        };
+
+        let x = Vector2::new(T::one(), T::zero());
+        let y = Vector2::new(T::zero(), T::one());
+        if separated(x) || separated(y) {
+            return false;
+        }
+
+        !self
+            .edges()
+            .any(|(a, b)| separated(Vector2::new(a.y - b.y, b.x - a.x)))
+    }
+
+    pub fn contains_rect(&self, rect: Rect<T>) -> bool {
+        corners(rect.normalize())
+            .iter()
+            .all(|&corner| self.contains(corner))
+    }
+}
+
+// Twice the signed area, positive for clockwise vertices with y pointing down.
+fn signed_area<T: Coordinate>(vertices: &[Vector2<T>]) -> T {
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .fold(T::zero(), |area, (&a, &b)| area + cross(a, b))
+}
+
+fn cross<T: Coordinate>(a: Vector2<T>, b: Vector2<T>) -> T {
+    a.x * b.y - a.y * b.x
+}
+
+fn corners<T: Coordinate>(rect: Rect<T>) -> [Vector2<T>; 4] {
+    [
+        Vector2::new(rect.left, rect.top),
+        Vector2::new(rect.right(), rect.top),
+        Vector2::new(rect.right(), rect.bottom()),
+        Vector2::new(rect.left, rect.bottom()),
+    ]
+}
+
+// Lowest and highest dot product of `points` with `axis`.
+fn project<T: Float + Coordinate>(points: &[Vector2<T>], axis: Vector2<T>) -> (T, T) {
+    points
+        .iter()
+        .map(|point| point.dot(axis))
+        .fold((T::infinity(), T::neg_infinity()), |(low, high), dot| {
+            (low.min(dot), high.max(dot))
+        })
+}
+
+/// A region the quadtree can be queried with, see `Quadtree::query_shape`.
+pub trait Shape<T> {
+    /// Whether any part of `bounds` may hold positions this shape contains.
+    fn overlaps(&self, bounds: Rect<T>) -> bool;
+
+    fn contains(&self, position: Vector2<T>) -> bool;
+
+    /// Whether every position inside `bounds`, far edges included, is contained. Queries take
+    /// whole quads passing this without testing their points one by one.
+    fn contains_rect(&self, _bounds: Rect<T>) -> bool {
+        false
+    }
+}
+
+impl<T, S: Shape<T> + ?Sized> Shape<T> for &S {
+    fn overlaps(&self, bounds: Rect<T>) -> bool {
+        (**self).overlaps(bounds)
+    }
+
+    fn contains(&self, position: Vector2<T>) -> bool {
+        (**self).contains(position)
+    }
+
+    fn contains_rect(&self, bounds: Rect<T>) -> bool {
+        (**self).contains_rect(bounds)
+    }
+}
+
+impl<T: Coordinate> Shape<T> for Rect<T> {
+    // Touching counts, a quad may own the points on its closed far edges.
+    fn overlaps(&self, bounds: Rect<T>) -> bool {
+        bounds.intersects(*self)
+    }
+
+    fn contains(&self, position: Vector2<T>) -> bool {
+        Rect::contains(*self, position)
+    }
+
+    // The far edges of `self` are open, so those of `bounds` have to stay clear of them.
+    fn contains_rect(&self, bounds: Rect<T>) -> bool {
+        let (outer, inner) = (self.normalize(), bounds.normalize());
+
+        inner.left >= outer.left
+            && inner.top >= outer.top
+            && inner.right() < outer.right()
+            && inner.bottom() < outer.bottom()
+    }
+}
+
+impl<T: Coordinate> Shape<T> for Circle<T> {
+    fn overlaps(&self, bounds: Rect<T>) -> bool {
+        self.intersects(bounds)
+    }
+
+    fn contains(&self, position: Vector2<T>) -> bool {
+        Circle::contains(*self, position)
+    }
+
+    fn contains_rect(&self, bounds: Rect<T>) -> bool {
+        corners(bounds.normalize())
+            .iter()
+            .all(|&corner| Circle::contains(*self, corner))
+    }
+}
+
+impl<T: Float + Coordinate> Shape<T> for ConvexPolygon<T> {
+    fn overlaps(&self, bounds: Rect<T>) -> bool {
+        self.intersects(bounds)
+    }
+
+    fn contains(&self, position: Vector2<T>) -> bool {
+        ConvexPolygon::contains(self, position)
+    }
+
+    fn contains_rect(&self, bounds: Rect<T>) -> bool {
+        ConvexPolygon::contains_rect(self, bounds)
+    }
 }
 
 fn min<T: PartialOrd>(i: T, n: T) -> T {
@@ -105,131 +539,465 @@ fn max<T: PartialOrd>(i: T, n: T) -> T {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Quadtree<T> {
-    pub bounds: Rect<T>,
-    pub capacity: usize,
-    max_capacity: usize,
-    pub children: Vec<PointIndex<T>>,
-    pub quads: Option<Vec<Quadtree<T>>>,
+pub(crate) fn rect_distance_squared<T: Coordinate>(rect: Rect<T>, position: Vector2<T>) -> T {
+    let rect = rect.normalize();
+
+    let dx = gap((rect.left, rect.right()), (position.x, position.x));
+    let dy = gap((rect.top, rect.bottom()), (position.y, position.y));
+
+    Vector2::new(dx, dy).length_squared()
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct PointIndex<T> {
-    pub position: Vector2<T>,
-    pub index: Option<usize>,
+pub(crate) fn rects_distance_squared<T: Coordinate>(a: Rect<T>, b: Rect<T>) -> T {
+    let (a, b) = (a.normalize(), b.normalize());
+
+    let dx = gap((a.left, a.right()), (b.left, b.right()));
+    let dy = gap((a.top, a.bottom()), (b.top, b.bottom()));
+
+    Vector2::new(dx, dy).length_squared()
 }
 
-impl<T: Float + PartialOrd + Add<Output = T> + Sub<Output = T> + Div<Output = T> + Copy>
-    Quadtree<T>
-{
-    pub fn new(bounds: Rect<T>, capacity: usize) -> Self {
-        Quadtree {
-            bounds,
-            capacity,
-            max_capacity: capacity,
-            children: Vec::with_capacity(capacity),
-            quads: None,
+// How far along `origin + direction * t` the ray first comes within `tolerance` of `rect`,
+// looking no further than `max_distance`. Grows `rect` by `tolerance` on every side, so it
+// may enter a little early near the corners, but never late.
+pub(crate) fn ray_entry<T: Float + Coordinate>(
+    rect: Rect<T>,
+    origin: Vector2<T>,
+    direction: Vector2<T>,
+    max_distance: T,
+    tolerance: T,
+) -> Option<T> {
+    let rect = rect.expand(tolerance);
+    let (mut enter, mut exit) = (T::zero(), max_distance);
+
+    for &(low, high, from, step) in &[
+        (rect.left, rect.right(), origin.x, direction.x),
+        (rect.top, rect.bottom(), origin.y, direction.y),
+    ] {
+        if step == T::zero() {
+            if from < low || from > high {
+                return None;
+            }
+        } else {
+            let (a, b) = ((low - from) / step, (high - from) / step);
+            enter = max(enter, min(a, b));
+            exit = min(exit, max(a, b));
         }
     }
 
-    pub fn set_bounds(mut self, bounds: Rect<T>) -> Self {
-        self.bounds = bounds;
-
-        self
+    if enter <= exit {
+        Some(enter)
+    } else {
+        None
     }
+}
 
-    pub fn set_capacity(mut self, capacity: usize) -> Self {
-        self.capacity = capacity;
-        self.children = Vec::with_capacity(capacity);
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Entry<T, D> {
+    pub position: Vector2<T>,
+    pub data: D,
+}
 
-        self
+impl<T, D> Entry<T, D> {
+    pub fn new(position: Vector2<T>, data: D) -> Self {
+        Entry { position, data }
     }
+}
 
-    pub fn set_quads(mut self) -> Self {
-        self.quads = Some(vec![Quadtree::new(self.bounds, self.capacity); 4]);
+/// The payload-less entry `Quadtree` defaults to. Its `index` field became `data` when entries
+/// took a generic payload, `index()` reads it under the old name.
+pub type PointIndex<T> = Entry<T, Option<usize>>;
 
-        self
+impl<T> Entry<T, Option<usize>> {
+    pub fn with_index(position: Vector2<T>, index: Option<usize>) -> Self {
+        Entry::new(position, index)
     }
 
-    pub fn insert(&mut self, location: PointIndex<T>) -> bool {
-        if !self.bounds.contains(location.position) {
-            return false;
-        }
+    pub fn index(&self) -> Option<usize> {
+        self.data
+    }
+}
+
+/// Whether points on the far (right and bottom) edges of the root `bounds` belong to the tree.
+///
+/// Quads are always half-open on their inner edges, so every position still lands in exactly one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Boundary {
+    #[default]
+    HalfOpen,
+    Closed,
+}
+
+/// Why `Quadtree::insert` or `RectQuadtree::insert` turned an item away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertError {
+    /// The item lies outside of the root `bounds`.
+    OutOfBounds,
+    /// The quad the item belongs in is full and too small to split any further, set a
+    /// `max_depth` to let full quads grow instead.
+    DepthExhausted,
+}
 
-        if self.children.len() < self.capacity && self.quads.is_none() {
-            self.children.push(location);
-            return true;
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::OutOfBounds => write!(f, "position is outside of the quadtree bounds"),
+            InsertError::DepthExhausted => {
+                write!(f, "quad is full and can not be divided any further")
+            }
         }
+    }
+}
+
+impl Error for InsertError {}
+
+/// Why `Quadtree::update` left an entry where it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateError {
+    /// No entry with that position and data is in the tree.
+    NotFound,
+    /// The entry could not be inserted at its new position.
+    Rejected(InsertError),
+}
 
-        if self.quads.is_none() {
-            self.divide();
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::NotFound => write!(f, "no such entry in the quadtree"),
+            UpdateError::Rejected(error) => write!(f, "entry can not be moved: {}", error),
         }
+    }
+}
 
-        for quad in self.quads.as_mut().unwrap().iter_mut() {
-            if quad.insert(location) {
-                return true;
-            }
+impl Error for UpdateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UpdateError::NotFound => None,
+            UpdateError::Rejected(error) => Some(error),
         }
+    }
+}
 
-        false
+/// Two entries reported together by `Quadtree::collision_pairs`.
+pub type Pair<'a, T, D> = (&'a Entry<T, D>, &'a Entry<T, D>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap_keeps_a_positive_height() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(5.0, 2.0, 10.0, 4.0);
+
+        assert_eq!(a.overlap(b), Some(Rect::new(5.0, 2.0, 5.0, 4.0)));
+        assert_eq!(b.overlap(a), Some(Rect::new(5.0, 2.0, 5.0, 4.0)));
+    }
+
+    #[test]
+    fn overlap_needs_a_shared_area() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+
+        assert_eq!(a.overlap(Rect::new(10.0, 0.0, 5.0, 5.0)), None);
+        assert_eq!(a.overlap(Rect::new(20.0, 20.0, 5.0, 5.0)), None);
+    }
+
+    #[test]
+    fn normalize_flips_negative_sizes() {
+        let rect = Rect::new(10.0, 20.0, -4.0, -6.0).normalize();
+
+        assert_eq!(rect, Rect::new(6.0, 14.0, 4.0, 6.0));
+        assert_eq!(Rect::new(10.0, 20.0, -4.0, -6.0).area(), 24.0);
+        assert!(Rect::new(10.0, 20.0, -4.0, -6.0).contains(Vector2::new(7.0, 15.0)));
+        assert_eq!(
+            Rect::new(10.0, 20.0, -4.0, -6.0).overlap(Rect::new(0.0, 0.0, 8.0, 16.0)),
+            Some(Rect::new(6.0, 14.0, 2.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn touching_edges() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+
+        assert!(rect.contains(Vector2::new(0.0, 0.0)));
+        assert!(!rect.contains(Vector2::new(10.0, 5.0)));
+        assert!(!rect.contains(Vector2::new(5.0, 10.0)));
+
+        let inside_on_edges = Rect::new(0.0, 0.0, 10.0, 5.0);
+        assert!(rect.contains_rect(inside_on_edges));
+        assert!(rect.contains_rect(rect));
+        assert!(!rect.contains_rect(Rect::new(1.0, 1.0, 10.0, 1.0)));
+
+        let beside = Rect::new(10.0, 0.0, 5.0, 5.0);
+        assert!(rect.intersects(beside));
+        assert!(beside.intersects(rect));
+        assert!(rect.intersects(Rect::new(10.0, 10.0, 1.0, 1.0)));
+        assert!(!rect.intersects(Rect::new(10.5, 0.0, 1.0, 1.0)));
+        assert!(!rect.contains_rect(beside));
+    }
+
+    #[test]
+    fn expand_collapses_onto_the_center() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 4.0);
+
+        assert_eq!(rect.expand(1.0), Rect::new(-1.0, -1.0, 12.0, 6.0));
+        assert_eq!(rect.expand(-1.0), Rect::new(1.0, 1.0, 8.0, 2.0));
+        assert_eq!(rect.expand(-3.0), Rect::new(3.0, 2.0, 4.0, 0.0));
+        assert_eq!(rect.expand(-10.0), Rect::new(5.0, 2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn union_covers_both() {
+        let a = Rect::new(0.0, 0.0, 2.0, 2.0);
+        let b = Rect::new(5.0, -3.0, 1.0, 1.0);
+
+        assert_eq!(a.union(b), Rect::new(0.0, -3.0, 6.0, 5.0));
+        assert_eq!(b.union(a), a.union(b));
+        assert_eq!(a.union(Rect::new(2.0, 2.0, -1.0, -1.0)), a);
+    }
+
+    #[test]
+    fn accessors() {
+        let rect = Rect::new(1.0, 2.0, 4.0, 6.0);
+
+        assert_eq!(rect.right(), 5.0);
+        assert_eq!(rect.bottom(), 8.0);
+        assert_eq!(rect.center(), Vector2::new(3.0, 5.0));
+        assert_eq!(rect.area(), 24.0);
+    }
+
+    #[test]
+    fn vector_arithmetic() {
+        let (a, b) = (Vector2::new(1.0, -2.0), Vector2::new(3.0, 4.0));
+
+        assert_eq!(a + b, Vector2::new(4.0, 2.0));
+        assert_eq!(a - b, Vector2::new(-2.0, -6.0));
+        assert_eq!(b * 0.5, Vector2::new(1.5, 2.0));
+        assert_eq!(b / 2.0, Vector2::new(1.5, 2.0));
+        assert_eq!(-a, Vector2::new(-1.0, 2.0));
+        assert_eq!(Vector2::new(7, 1) - Vector2::new(2, 4), Vector2::new(5, -3));
+    }
+
+    #[test]
+    fn normalize_keeps_the_zero_vector() {
+        assert_eq!(Vector2::new(3.0, 4.0).normalize(), Vector2::new(0.6, 0.8));
+        assert_eq!(Vector2::new(0.0, -5.0).normalize(), Vector2::new(0.0, -1.0));
+        assert_eq!(Vector2::new(0.0, 0.0).normalize(), Vector2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn lerp_runs_between_the_ends() {
+        let (a, b) = (Vector2::new(2.0, 8.0), Vector2::new(6.0, 0.0));
+
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.25), Vector2::new(3.0, 6.0));
+        assert_eq!(a.lerp(b, 2.0), Vector2::new(10.0, -8.0));
+    }
+
+    #[test]
+    fn vector_conversions() {
+        assert_eq!(Vector2::from((1, 2)), Vector2::new(1, 2));
+        assert_eq!(Vector2::from([3, 4]), Vector2::new(3, 4));
+        assert_eq!(<(i32, i32)>::from(Vector2::new(5, 6)), (5, 6));
+        assert_eq!(<[i32; 2]>::from(Vector2::new(7, 8)), [7, 8]);
+    }
+
+    #[test]
+    fn circle_contains_its_edge() {
+        let circle = Circle::new(Vector2::new(1.0, 1.0), 5.0);
+
+        assert!(circle.contains(Vector2::new(1.0, 1.0)));
+        assert!(circle.contains(Vector2::new(4.0, 5.0)));
+        assert!(!circle.contains(Vector2::new(4.0, 5.5)));
+        assert!(!circle.contains(Vector2::new(-4.5, 1.0)));
+    }
+
+    #[test]
+    fn circle_touching_a_corner() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let touching = Circle::new(Vector2::new(13.0, 14.0), 5.0);
+        let short = Circle::new(Vector2::new(13.0, 14.0), 4.9);
+        let diagonal = Circle::new(Vector2::new(-3.0, -4.0), 5.0);
+
+        assert!(touching.intersects(rect));
+        assert!(rect.intersects_circle(touching));
+        assert!(!short.intersects(rect));
+        assert!(!rect.intersects_circle(short));
+        assert!(rect.intersects_circle(diagonal));
+    }
+
+    #[test]
+    fn circle_inside_a_rect() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let circle = Circle::new(Vector2::new(5.0, 5.0), 2.0);
+
+        assert!(circle.intersects(rect));
+        assert!(rect.intersects_circle(circle));
+        assert!(circle.contains(rect.center()));
+        assert!(rect.intersects_circle(Circle::new(Vector2::new(5.0, 5.0), 20.0)));
     }
 
-    fn divide(&mut self) {
-        if self.quads.is_some() {
-            return;
+    #[test]
+    fn circle_missing_a_rect() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+
+        // Past a side, and off a corner while within reach of both side lines.
+        for &circle in &[
+            Circle::new(Vector2::new(15.0, 5.0), 4.0),
+            Circle::new(Vector2::new(5.0, -3.0), 2.5),
+            Circle::new(Vector2::new(13.0, 13.0), 4.0),
+        ] {
+            assert!(!circle.intersects(rect));
+            assert!(!rect.intersects_circle(circle));
         }
+        assert!(rect.intersects_circle(Circle::new(Vector2::new(15.0, 5.0), 5.0)));
+    }
+}
 
-        let x = self.bounds.left;
-        let y = self.bounds.top;
-        let w = self.bounds.width / T::from(2.0).unwrap();
-        let h = self.bounds.height / T::from(2.0).unwrap();
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
 
-        self.quads = Some(vec![Quadtree::new(self.bounds, self.max_capacity); 4]);
+    // What a text and a binary format each read back.
+    fn round_trip<V: Serialize + DeserializeOwned>(value: &V) -> [V; 2] {
+        let json = serde_json::to_string(value).unwrap();
+        let binary = bincode::serialize(value).unwrap();
 
-        // todo: better way to do this?
-        for (idx, quad) in self.quads.as_mut().unwrap().iter_mut().enumerate() {
-            match idx {
-                0 => quad.bounds = Rect::new(x, y, w, h),         // NW
-                1 => quad.bounds = Rect::new(x + w, y, w, h),     // NE
-                2 => quad.bounds = Rect::new(x + w, y + h, w, h), // SE
-                3 => quad.bounds = Rect::new(x, y + h, w, h),     // SW
-                _ => {
-                    panic!("More quads than quarters!");
-                }
-            }
+        [
+            serde_json::from_str(&json).unwrap(),
+            bincode::deserialize(&binary).unwrap(),
+        ]
+    }
+
+    // Snapshots hold every setting and table of a tree, so equal bytes mean equal trees.
+    fn snapshot<B, E>(tree: &SpatialTree<B, E>) -> Vec<u8>
+    where
+        B: Bounds + Encode,
+        E: Positioned<Point = B::Point> + Encode,
+    {
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes).unwrap();
+
+        bytes
+    }
+
+    fn assert_same_tree<B, E>(read: &SpatialTree<B, E>, tree: &SpatialTree<B, E>)
+    where
+        B: Bounds + Encode,
+        E: Positioned<Point = B::Point> + Encode,
+    {
+        assert_eq!(read.capacity, tree.capacity);
+        assert_eq!(read.max_capacity, tree.max_capacity);
+        assert_eq!(read.max_depth, tree.max_depth);
+        assert_eq!(read.free, tree.free);
+        assert_eq!(snapshot(read), snapshot(tree));
+    }
+
+    #[test]
+    fn geometry() {
+        let vector = Vector2::new(1.5f32, -2.25);
+        let rect = Rect::new(-10.0f64, 4.5, 20.0, 0.125);
+        let entry = Entry::new(Vector2::new(3, 4), Some(7usize));
+
+        assert_eq!(round_trip(&vector), [vector; 2]);
+        assert_eq!(round_trip(&rect), [rect; 2]);
+        for read in &round_trip(&entry) {
+            assert_eq!((read.position, read.data), (entry.position, entry.data));
         }
     }
 
-    // todo: this wasn't in use yet, so... quiet
-    // fn _query(&self, range: Rect<f32>) -> Vec<Vector2f> {
-    //     let mut children_in_range = Vec::new();
+    #[test]
+    fn quadtree() {
+        let bounds = Rect::new(0.0, 0.0, 64.0, 64.0);
+        let mut tree: Quadtree<f32> = Quadtree::new(bounds, 4)
+            .set_capacity(2)
+            .set_max_depth(5)
+            .set_boundary(Boundary::Closed);
+        let positions: Vec<_> = (0..100)
+            .map(|idx| Vector2::new((idx % 10) as f32 * 6.4, (idx / 10) as f32 * 6.4))
+            .collect();
+        for (idx, &position) in positions.iter().enumerate() {
+            tree.insert(PointIndex::with_index(position, Some(idx)))
+                .unwrap();
+        }
+        for (idx, &position) in positions.iter().enumerate().skip(20) {
+            tree.remove(position, &Some(idx)).unwrap();
+        }
+        assert!(!tree.free.is_empty());
 
-    //     if self.bounds.intersection(&range).is_none() {
-    //         return children_in_range;
-    //     }
+        for mut read in round_trip(&tree) {
+            assert_same_tree(&read, &tree);
 
-    //     for child in self.children.iter() {
-    //         if range.contains(*child) {
-    //             children_in_range.push(*child);
-    //         }
-    //     }
+            // Released blocks are reused after reading just like before.
+            read.insert(PointIndex::with_index(positions[99], Some(99)))
+                .unwrap();
+            assert_eq!(read.query(bounds).len(), 21);
+        }
+    }
 
-    //     if self.quads.is_none() {
-    //         return children_in_range;
-    //     }
+    #[test]
+    fn corrupt_trees_are_rejected() {
+        let mut tree: Quadtree<f32> = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 1);
+        for &at in &[10.0, 20.0, 500.0] {
+            tree.insert(PointIndex::with_index(Vector2::new(at, at), None))
+                .unwrap();
+        }
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(serde_json::from_str::<Quadtree<f32>>(&json).is_ok());
+
+        // A leaf pointed at children that do not exist.
+        let leaf = format!("\"children\":{}", u32::MAX);
+        let broken = json.replacen(&leaf, "\"children\":7", 1);
+        assert_ne!(broken, json);
+        let error = serde_json::from_str::<Quadtree<f32>>(&broken).unwrap_err();
+        assert!(error.to_string().contains("node out of range"), "{}", error);
+
+        let emptied = json.replacen("\"links\":[", "\"links\":[],\"unused\":[", 1);
+        assert!(serde_json::from_str::<Quadtree<f32>>(&emptied).is_err());
+    }
 
-    //     for quad in self.quads.as_ref().unwrap().iter() {
-    //         children_in_range.append(&mut quad._query(range));
-    //     }
+    #[test]
+    fn octree() {
+        let bounds = Aabb3::new(Vector3::new(0, 0, 0), Vector3::new(16, 16, 16));
+        let mut tree: Octree<i32, u16> = Octree::new(bounds, 2).set_max_depth(3);
+        for idx in 0..64 {
+            let position = Vector3::new(idx % 4 * 4, idx / 4 % 4 * 4, idx / 16 * 4);
+            tree.insert(Entry3::new(position, idx as u16)).unwrap();
+        }
+        for idx in 0..60 {
+            let position = Vector3::new(idx % 4 * 4, idx / 4 % 4 * 4, idx / 16 * 4);
+            tree.remove(position, &(idx as u16)).unwrap();
+        }
+        assert!(!tree.free.is_empty());
 
-    //     children_in_range
-    // }
+        for read in round_trip(&tree) {
+            assert_same_tree(&read, &tree);
+            assert_eq!(read.query(bounds).len(), 4);
+        }
+    }
 
-    // fn _remove_nearest(&mut self, _location: Vector2f) {}
+    #[test]
+    fn orthtree() {
+        let bounds = Aabb::new([0.0; 4], [8.0; 4]);
+        let position = |idx: u8| {
+            [0, 1, 2, 3].map(|axis| f64::from(idx >> axis & 1) * 4.0 + f64::from(idx >> 4) + 1.0)
+        };
+        let mut tree: Orthtree<f64, 4, u8> = Orthtree::new(bounds, 1);
+        for idx in 0..32 {
+            tree.insert(Item::new(position(idx), idx)).unwrap();
+        }
+        for idx in 8..32 {
+            tree.remove(position(idx), &idx).unwrap();
+        }
+        assert!(!tree.free.is_empty());
 
-    pub fn clear(&mut self) {
-        self.children.clear();
-        self.quads = None;
+        for read in round_trip(&tree) {
+            assert_same_tree(&read, &tree);
+            assert_eq!(read.query(bounds).len(), 8);
+        }
     }
 }
@@ -0,0 +1,339 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::coordinate::difference;
+use crate::tree::{Bounds, Positioned, SpatialTree};
+use crate::{Aabb, Add, Coordinate, Float, Sub};
+use std::array;
+use std::ops::Mul;
+
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Vector3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T> Vector3<T> {
+    pub fn new(x: T, y: T, z: T) -> Self {
+        Vector3 { x, y, z }
+    }
+}
+
+impl<T: Coordinate> Vector3<T> {
+    pub fn dot(self, other: Vector3<T>) -> T {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn length_squared(self) -> T {
+        self.dot(self)
+    }
+
+    pub fn distance_squared(self, other: Vector3<T>) -> T {
+        Vector3::new(
+            difference(self.x, other.x),
+            difference(self.y, other.y),
+            difference(self.z, other.z),
+        )
+        .length_squared()
+    }
+}
+
+impl<T: Float + Coordinate> Vector3<T> {
+    pub fn length(self) -> T {
+        self.length_squared().sqrt()
+    }
+
+    pub fn distance(self, other: Vector3<T>) -> T {
+        self.distance_squared(other).sqrt()
+    }
+}
+
+impl<T: Add<Output = T>> Add for Vector3<T> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Vector3<T> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl<T: Mul<Output = T> + Copy> Mul<T> for Vector3<T> {
+    type Output = Self;
+
+    fn mul(self, scalar: T) -> Self {
+        Vector3::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+impl<T> From<(T, T, T)> for Vector3<T> {
+    fn from((x, y, z): (T, T, T)) -> Self {
+        Vector3::new(x, y, z)
+    }
+}
+
+impl<T> From<[T; 3]> for Vector3<T> {
+    fn from([x, y, z]: [T; 3]) -> Self {
+        Vector3::new(x, y, z)
+    }
+}
+
+impl<T> From<Vector3<T>> for [T; 3] {
+    fn from(vector: Vector3<T>) -> Self {
+        [vector.x, vector.y, vector.z]
+    }
+}
+
+pub type Vector3f = Vector3<f32>;
+
+/// Axis aligned box, the 3D counterpart of `Rect`. Sizes are expected not to be negative.
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aabb3<T> {
+    pub min: Vector3<T>,
+    pub size: Vector3<T>,
+}
+
+impl<T: Coordinate> Aabb3<T> {
+    pub fn new(min: Vector3<T>, size: Vector3<T>) -> Self {
+        Aabb3 { min, size }
+    }
+
+    pub fn max(self) -> Vector3<T> {
+        self.min + self.size
+    }
+
+    pub fn center(self) -> Vector3<T> {
+        Vector3::from(Aabb::from(self).center())
+    }
+
+    /// Half-open like `Rect::contains`, the far faces are left out.
+    pub fn contains(self, position: Vector3<T>) -> bool {
+        self.holds(position, 0)
+    }
+
+    /// Touching counts as intersecting.
+    pub fn intersects(self, other: Aabb3<T>) -> bool {
+        let (a, b) = (self.max(), other.max());
+
+        self.min.x <= b.x
+            && other.min.x <= a.x
+            && self.min.y <= b.y
+            && other.min.y <= a.y
+            && self.min.z <= b.z
+            && other.min.z <= a.z
+    }
+
+    /// Splits into eighths, the index of each one having bit 0 set for the upper half along x,
+    /// bit 1 along y and bit 2 along z.
+    pub fn octants(self) -> [Aabb3<T>; 8] {
+        array::from_fn(|idx| self.child(idx))
+    }
+}
+
+impl<T: Coordinate> Bounds for Aabb3<T> {
+    type Point = Vector3<T>;
+
+    const DIMENSIONS: usize = 3;
+
+    fn child(&self, idx: usize) -> Self {
+        Aabb3::from(Aabb::from(*self).child(idx))
+    }
+
+    fn far_edges(child: usize) -> u8 {
+        Aabb::<T, 3>::far_edges(child)
+    }
+
+    fn holds(&self, point: Vector3<T>, closed: u8) -> bool {
+        Aabb::from(*self).holds(point.into(), closed)
+    }
+
+    fn can_divide(&self) -> bool {
+        Aabb::from(*self).can_divide()
+    }
+
+    fn exact_split(&self) -> bool {
+        Aabb::from(*self).exact_split()
+    }
+
+    fn guess_child(&self, point: Vector3<T>) -> usize {
+        Aabb::from(*self).guess_child(point.into())
+    }
+
+    fn grow(&self, point: Vector3<T>, closed: u8) -> Option<Self> {
+        Aabb::from(*self)
+            .grow(point.into(), closed)
+            .map(Aabb3::from)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Entry3<T, D> {
+    pub position: Vector3<T>,
+    pub data: D,
+}
+
+impl<T, D> Entry3<T, D> {
+    pub fn new(position: Vector3<T>, data: D) -> Self {
+        Entry3 { position, data }
+    }
+}
+
+pub type PointIndex3<T> = Entry3<T, Option<usize>>;
+
+impl<T: Copy + PartialEq, D> Positioned for Entry3<T, D> {
+    type Point = Vector3<T>;
+    type Data = D;
+
+    fn position(&self) -> Vector3<T> {
+        self.position
+    }
+
+    fn set_position(&mut self, position: Vector3<T>) {
+        self.position = position;
+    }
+
+    fn data(&self) -> &D {
+        &self.data
+    }
+}
+
+/// Point octree sharing its implementation with `Quadtree`, see `SpatialTree`.
+pub type Octree<T, D = Option<usize>> = SpatialTree<Aabb3<T>, Entry3<T, D>>;
+
+impl<T: Coordinate, D> Octree<T, D> {
+    pub fn set_octants(mut self) -> Self {
+        self.divide(0);
+
+        self
+    }
+
+    pub fn query(&self, range: Aabb3<T>) -> Vec<&Entry3<T, D>> {
+        self.collect_where(
+            |bounds| bounds.intersects(range),
+            |point| range.contains(point.position),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{layout, sorted};
+    use crate::InsertError;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn bounds() -> Aabb3<f32> {
+        Aabb3::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(80.0, 60.0, 40.0))
+    }
+
+    fn points(count: usize, seed: u64) -> Vec<PointIndex3<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        (0..count)
+            .map(|idx| {
+                let position = Vector3::new(
+                    rng.gen_range(0.0, 80.0),
+                    rng.gen_range(0.0, 60.0),
+                    rng.gen_range(0.0, 40.0),
+                );
+                Entry3::new(position, Some(idx))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn octants_cover_the_box() {
+        let octants = bounds().octants();
+
+        assert_eq!(octants[0].min, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(octants[7].min, Vector3::new(40.0, 30.0, 20.0));
+        assert!(octants
+            .iter()
+            .all(|octant| octant.size == Vector3::new(40.0, 30.0, 20.0)));
+        for (idx, octant) in octants.iter().enumerate() {
+            let corner = octant.min;
+            assert_eq!(bounds().guess_child(corner), idx);
+            assert_eq!(octants.iter().filter(|o| o.contains(corner)).count(), 1);
+        }
+    }
+
+    #[test]
+    fn splits_into_eight() {
+        let tree: Octree<f32> = Octree::new(bounds(), 4).set_octants();
+
+        assert_eq!(tree.nodes.len(), 9);
+        assert!(tree.nodes[1..].iter().all(|node| node.depth == 1));
+    }
+
+    #[test]
+    fn query_matches_brute_force() {
+        let points = points(3000, 31);
+        let mut tree = Octree::new(bounds(), 4);
+        for point in &points {
+            tree.insert(*point).unwrap();
+        }
+        assert_eq!(tree.len(), 3000);
+
+        let mut rng = StdRng::seed_from_u64(32);
+        for _ in 0..100 {
+            let range = Aabb3::new(
+                Vector3::new(
+                    rng.gen_range(-10.0, 80.0),
+                    rng.gen_range(-10.0, 60.0),
+                    rng.gen_range(-10.0, 40.0),
+                ),
+                Vector3::new(
+                    rng.gen_range(0.0, 40.0),
+                    rng.gen_range(0.0, 40.0),
+                    rng.gen_range(0.0, 40.0),
+                ),
+            );
+            let expected = points.iter().filter(|p| range.contains(p.position));
+
+            assert_eq!(sorted(tree.query(range)), sorted(expected));
+        }
+    }
+
+    #[test]
+    fn from_points_matches_insert() {
+        let points = points(3000, 33);
+        let mut inserted = Octree::new(bounds(), 4);
+        for point in &points {
+            inserted.insert(*point).unwrap();
+        }
+        let loaded = Octree::from_points(bounds(), 4, points.iter().copied());
+
+        assert_eq!(layout(&loaded), layout(&inserted));
+    }
+
+    #[test]
+    fn remove_and_clear() {
+        let points = points(500, 34);
+        let mut tree = Octree::from_points(bounds(), 4, points.iter().copied());
+        assert_eq!(
+            tree.insert(Entry3::new(Vector3::new(80.0, 0.0, 0.0), None)),
+            Err(InsertError::OutOfBounds)
+        );
+
+        for point in &points[100..] {
+            assert!(tree.remove(point.position, &point.data).is_some());
+        }
+        assert_eq!(sorted(tree.query(bounds())), (0..100).collect::<Vec<_>>());
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.nodes.len(), 1);
+        assert_eq!(tree.bounds(), bounds());
+    }
+}
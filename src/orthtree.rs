@@ -0,0 +1,303 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::tree::{Bounds, Positioned, SpatialTree};
+use crate::{Aabb3, Coordinate, Rect, Vector3};
+use std::array;
+
+/// Axis aligned box in `N` dimensions, splitting into `2^N` children.
+///
+/// `Rect` and `Aabb3` are the two and three dimensional cases with named fields, their `Bounds`
+/// impls go through this one. Far edge masks are a `u8`, so `N` can be at most 8.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))
+)]
+pub struct Aabb<T, const N: usize> {
+    #[cfg_attr(feature = "serde", serde(with = "array_serde"))]
+    pub min: [T; N],
+    #[cfg_attr(feature = "serde", serde(with = "array_serde"))]
+    pub size: [T; N],
+}
+
+/// The one dimensional box, split in halves by a `BinaryTree`.
+pub type Interval<T> = Aabb<T, 1>;
+
+impl<T: Coordinate, const N: usize> Aabb<T, N> {
+    pub fn new(min: [T; N], size: [T; N]) -> Self {
+        Aabb { min, size }
+    }
+
+    pub fn max(self) -> [T; N] {
+        array::from_fn(|axis| self.min[axis] + self.size[axis])
+    }
+
+    pub fn center(self) -> [T; N] {
+        array::from_fn(|axis| self.min[axis] + self.size[axis].half())
+    }
+
+    pub fn normalize(self) -> Self {
+        let mut bounds = self;
+        for (min, size) in bounds.min.iter_mut().zip(&mut bounds.size) {
+            if *size < T::zero() {
+                *min = *min + *size;
+                *size = T::zero() - *size;
+            }
+        }
+
+        bounds
+    }
+
+    /// Half-open like `Rect::contains`, the far edges are left out.
+    pub fn contains(self, point: [T; N]) -> bool {
+        self.holds(point, 0)
+    }
+
+    /// Touching counts as intersecting.
+    pub fn intersects(self, other: Aabb<T, N>) -> bool {
+        let (a, b) = (self.normalize(), other.normalize());
+        let (a_max, b_max) = (a.max(), b.max());
+
+        (0..N).all(|axis| a.min[axis] <= b_max[axis] && b.min[axis] <= a_max[axis])
+    }
+}
+
+impl<T: Coordinate, const N: usize> Bounds for Aabb<T, N> {
+    type Point = [T; N];
+
+    // Checked wherever the tree uses the dimension count, so a box the masks cannot describe fails
+    // to compile instead of truncating its far edges.
+    const DIMENSIONS: usize = {
+        assert!(N <= 8, "far edge masks only have room for 8 axes");
+        N
+    };
+
+    // Bit `axis` of a child's index is set when it takes the upper half along that axis, which
+    // also gets the odd unit of an integer size.
+    fn child(&self, idx: usize) -> Self {
+        debug_assert!(idx < Self::CHILDREN);
+        let bounds = self.normalize();
+        let lower: [T; N] = array::from_fn(|axis| bounds.size[axis].half());
+        let upper = |axis: usize| idx >> axis & 1 != 0;
+
+        let min = array::from_fn(|axis| {
+            if upper(axis) {
+                bounds.min[axis] + lower[axis]
+            } else {
+                bounds.min[axis]
+            }
+        });
+        let size = array::from_fn(|axis| {
+            if upper(axis) {
+                bounds.size[axis] - lower[axis]
+            } else {
+                lower[axis]
+            }
+        });
+
+        Aabb::new(min, size)
+    }
+
+    fn far_edges(child: usize) -> u8 {
+        debug_assert!(child < Self::CHILDREN);
+        child as u8
+    }
+
+    fn holds(&self, point: [T; N], closed: u8) -> bool {
+        let bounds = self.normalize();
+        let max = bounds.max();
+
+        (0..N).all(|axis| {
+            point[axis] >= bounds.min[axis]
+                && (point[axis] < max[axis] || closed >> axis & 1 != 0 && point[axis] == max[axis])
+        })
+    }
+
+    // An axis too small to split leaves one of its halves empty, which still lets a one unit wide
+    // integer strip divide along its length.
+    fn can_divide(&self) -> bool {
+        let center = self.center();
+        let bounds = self.normalize();
+        let max = bounds.max();
+
+        (0..N).any(|axis| bounds.min[axis] < center[axis] && center[axis] < max[axis])
+    }
+
+    // Lower halves always end exactly where the upper ones start, so only the far edges of the
+    // upper halves can be off, and only for a box given with negative sizes or through rounding.
+    fn exact_split(&self) -> bool {
+        let bounds = self.normalize();
+        let (center, max) = (self.center(), bounds.max());
+
+        (0..N).all(|axis| {
+            let lower = bounds.size[axis].half();
+            let start = bounds.min[axis] + lower;
+
+            self.size[axis] >= T::zero()
+                && center[axis] == start
+                && start + (bounds.size[axis] - lower) == max[axis]
+        })
+    }
+
+    fn guess_child(&self, point: [T; N]) -> usize {
+        let center = self.center();
+
+        (0..N)
+            .filter(|&axis| point[axis] >= center[axis])
+            .map(|axis| 1 << axis)
+            .sum()
+    }
+
+    // Gives up once a coordinate would overflow, or stop being finite, which also covers points
+    // that are not finite themselves.
+    fn grow(&self, point: [T; N], closed: u8) -> Option<Self> {
+        let mut bounds = self.normalize();
+        if bounds.size.iter().any(|size| size.is_zero()) {
+            return None;
+        }
+
+        while !bounds.holds(point, closed) {
+            let axes = point.iter().zip(&mut bounds.min).zip(&mut bounds.size);
+            for ((&value, min), size) in axes {
+                if value < *min {
+                    *min = min.checked_sub(*size)?;
+                }
+                *size = size.checked_add(*size)?;
+                min.checked_add(*size)?;
+            }
+        }
+
+        Some(bounds)
+    }
+}
+
+impl<T> From<Rect<T>> for Aabb<T, 2> {
+    fn from(rect: Rect<T>) -> Self {
+        Aabb {
+            min: [rect.left, rect.top],
+            size: [rect.width, rect.height],
+        }
+    }
+}
+
+impl<T> From<Aabb<T, 2>> for Rect<T> {
+    fn from(Aabb { min, size }: Aabb<T, 2>) -> Self {
+        let ([left, top], [width, height]) = (min, size);
+
+        Rect {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+}
+
+impl<T> From<Aabb3<T>> for Aabb<T, 3> {
+    fn from(bounds: Aabb3<T>) -> Self {
+        Aabb {
+            min: bounds.min.into(),
+            size: bounds.size.into(),
+        }
+    }
+}
+
+impl<T> From<Aabb<T, 3>> for Aabb3<T> {
+    fn from(Aabb { min, size }: Aabb<T, 3>) -> Self {
+        Aabb3 {
+            min: Vector3::from(min),
+            size: Vector3::from(size),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: Serialize, D: Serialize",
+        deserialize = "T: Deserialize<'de>, D: Deserialize<'de>"
+    ))
+)]
+pub struct Item<T, D, const N: usize> {
+    #[cfg_attr(feature = "serde", serde(with = "array_serde"))]
+    pub position: [T; N],
+    pub data: D,
+}
+
+impl<T, D, const N: usize> Item<T, D, N> {
+    pub fn new(position: [T; N], data: D) -> Self {
+        Item { position, data }
+    }
+}
+
+impl<T: Copy + PartialEq, D, const N: usize> Positioned for Item<T, D, N> {
+    type Point = [T; N];
+    type Data = D;
+
+    fn position(&self) -> [T; N] {
+        self.position
+    }
+
+    fn set_position(&mut self, position: [T; N]) {
+        self.position = position;
+    }
+
+    fn data(&self) -> &D {
+        &self.data
+    }
+}
+
+/// Point tree over any number of dimensions, with the same insert and query rules as `Quadtree`
+/// and `Octree`.
+pub type Orthtree<T, const N: usize, D = Option<usize>> = SpatialTree<Aabb<T, N>, Item<T, D, N>>;
+
+/// One dimensional `Orthtree`, halving intervals on every split.
+pub type BinaryTree<T, D = Option<usize>> = Orthtree<T, 1, D>;
+
+impl<T: Coordinate, D, const N: usize> Orthtree<T, N, D> {
+    pub fn set_children(mut self) -> Self {
+        self.divide(0);
+
+        self
+    }
+
+    pub fn query(&self, range: Aabb<T, N>) -> Vec<&Item<T, D, N>> {
+        self.collect_where(
+            |bounds| bounds.intersects(range),
+            |point| range.contains(point.position),
+        )
+    }
+}
+
+// Serde only covers arrays up to a fixed length, these go through a sequence instead.
+#[cfg(feature = "serde")]
+mod array_serde {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::convert::TryInto;
+
+    pub fn serialize<S, T, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        array[..].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        let len = items.len();
+
+        items
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"one value per axis"))
+    }
+}
@@ -0,0 +1,1559 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::{slice, vec};
+
+use crate::tree::{Bounds, Node, Positioned, SpatialTree, NONE};
+use crate::{
+    ray_entry, rect_distance_squared, rects_distance_squared, Aabb, Circle, ConvexPolygon,
+    Coordinate, Entry, Float, Pair, Rect, Shape, Vector2,
+};
+
+/// Point quadtree keeping all of its nodes in one `Vec` and all of its points in another, see
+/// `SpatialTree`.
+pub type Quadtree<T, D = Option<usize>> = SpatialTree<Rect<T>, Entry<T, D>>;
+
+// Far edges of the `Rect::quarters`, bit 0 for the right edge and bit 1 for the bottom one. This
+// is also where `Aabb` puts each quarter among its children.
+const FAR_EDGES: [u8; 4] = [0b00, 0b01, 0b11, 0b10];
+
+// Quadtrees keep their quarters in `Rect::quarters` order, the rest is left to `Aabb`.
+impl<T: Coordinate> Bounds for Rect<T> {
+    type Point = Vector2<T>;
+
+    const DIMENSIONS: usize = 2;
+
+    fn child(&self, idx: usize) -> Self {
+        Rect::from(Aabb::from(*self).child(FAR_EDGES[idx] as usize))
+    }
+
+    fn far_edges(child: usize) -> u8 {
+        FAR_EDGES[child]
+    }
+
+    fn holds(&self, point: Vector2<T>, closed: u8) -> bool {
+        Aabb::from(*self).holds(point.into(), closed)
+    }
+
+    fn can_divide(&self) -> bool {
+        Aabb::from(*self).can_divide()
+    }
+
+    fn exact_split(&self) -> bool {
+        Aabb::from(*self).exact_split()
+    }
+
+    // Swapping the last two quarters is its own inverse, so the table maps back as well.
+    fn guess_child(&self, point: Vector2<T>) -> usize {
+        FAR_EDGES[Aabb::from(*self).guess_child(point.into())] as usize
+    }
+
+    fn grow(&self, point: Vector2<T>, closed: u8) -> Option<Self> {
+        Aabb::from(*self).grow(point.into(), closed).map(Rect::from)
+    }
+}
+
+impl<T: Copy + PartialEq, D> Positioned for Entry<T, D> {
+    type Point = Vector2<T>;
+    type Data = D;
+
+    fn position(&self) -> Vector2<T> {
+        self.position
+    }
+
+    fn set_position(&mut self, position: Vector2<T>) {
+        self.position = position;
+    }
+
+    fn data(&self) -> &D {
+        &self.data
+    }
+}
+
+impl<T: Coordinate, D> Quadtree<T, D> {
+    pub fn set_quads(mut self) -> Self {
+        self.divide(0);
+
+        self
+    }
+
+    pub fn root(&self) -> NodeRef<'_, T, D> {
+        NodeRef {
+            tree: self,
+            node: 0,
+        }
+    }
+
+    /// Every entry in the tree, in no particular order.
+    pub fn iter(&self) -> Iter<'_, T, D> {
+        Iter {
+            points: self.points.iter(),
+        }
+    }
+
+    /// Every entry in the tree with mutable access to its data. Positions stay read only, see
+    /// `update` for moving entries.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, D> {
+        IterMut {
+            points: self.points.iter_mut(),
+        }
+    }
+
+    /// Every quad from the root down, each one before its own quads.
+    pub fn nodes(&self) -> Nodes<'_, T, D> {
+        Nodes {
+            tree: self,
+            stack: vec![0],
+        }
+    }
+
+    /// The quads that have not been divided, in the same order as `nodes`.
+    pub fn leaves(&self) -> Leaves<'_, T, D> {
+        Leaves {
+            nodes: self.nodes(),
+        }
+    }
+
+    /// Walks the quads depth first, each one before its own quads, letting `visitor` decide
+    /// whether to go on below every quad it is shown.
+    pub fn visit<V: Visitor<T, D> + ?Sized>(&self, visitor: &mut V) {
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            match visitor.visit(NodeRef { tree: self, node }) {
+                Visit::Continue => {
+                    let quads = self.nodes[node as usize].children;
+                    if quads != NONE {
+                        stack.extend((quads..quads + 4).rev());
+                    }
+                }
+                Visit::SkipChildren => {}
+                Visit::Stop => return,
+            }
+        }
+    }
+
+    pub fn query(&self, range: Rect<T>) -> Vec<&Entry<T, D>> {
+        self.query_iter(range).collect()
+    }
+
+    pub fn query_iter(&self, range: Rect<T>) -> Query<'_, T, D> {
+        self.query_shape(range)
+    }
+
+    pub fn query_radius(&self, center: Vector2<T>, radius: T) -> Vec<&Entry<T, D>> {
+        self.query_shape(Circle::new(center, radius)).collect()
+    }
+
+    pub fn query_shape<S: Shape<T>>(&self, shape: S) -> Query<'_, T, D, S> {
+        let mut query = Query {
+            tree: self,
+            shape,
+            stack: Vec::new(),
+            next: NONE,
+            inside: false,
+        };
+        query.visit(0, false);
+
+        query
+    }
+
+    /// Every unordered pair of entries no further than `radius` apart, each reported once.
+    pub fn collision_pairs(&self, radius: T) -> Vec<Pair<'_, T, D>> {
+        let mut pairs = Vec::new();
+        self.pairs_within(0, radius * radius, &mut pairs);
+
+        pairs
+    }
+
+    fn pairs_within<'a>(&'a self, node: u32, range: T, pairs: &mut Vec<Pair<'a, T, D>>) {
+        for a in self.node_points(node) {
+            let after = self.links[a as usize].next;
+            let a = &self.points[a as usize];
+
+            for b in self.chain(after) {
+                let b = &self.points[b as usize];
+                if a.position.distance_squared(b.position) <= range {
+                    pairs.push((a, b));
+                }
+            }
+        }
+
+        let quads = self.nodes[node as usize].children;
+        if quads != NONE {
+            for quad in quads..quads + 4 {
+                self.pairs_against(quad, node, range, pairs);
+                self.pairs_within(quad, range, pairs);
+
+                for other in quad + 1..quads + 4 {
+                    self.pairs_between(quad, other, range, pairs);
+                }
+            }
+        }
+    }
+
+    // Pairs every entry below `node` with the entries of `owner` itself.
+    fn pairs_against<'a>(
+        &'a self,
+        node: u32,
+        owner: u32,
+        range: T,
+        pairs: &mut Vec<Pair<'a, T, D>>,
+    ) {
+        let bounds = self.nodes[node as usize].bounds;
+        if self
+            .node_points(owner)
+            .all(|a| rect_distance_squared(bounds, self.points[a as usize].position) > range)
+        {
+            return;
+        }
+
+        for a in self.node_points(owner) {
+            let a = &self.points[a as usize];
+
+            for b in self.node_points(node) {
+                let b = &self.points[b as usize];
+                if a.position.distance_squared(b.position) <= range {
+                    pairs.push((a, b));
+                }
+            }
+        }
+
+        let quads = self.nodes[node as usize].children;
+        if quads != NONE {
+            for quad in quads..quads + 4 {
+                self.pairs_against(quad, owner, range, pairs);
+            }
+        }
+    }
+
+    // Pairs every entry below `node` with every entry below the disjoint `other`.
+    fn pairs_between<'a>(
+        &'a self,
+        node: u32,
+        other: u32,
+        range: T,
+        pairs: &mut Vec<Pair<'a, T, D>>,
+    ) {
+        let (a, b) = (&self.nodes[node as usize], &self.nodes[other as usize]);
+        if rects_distance_squared(a.bounds, b.bounds) > range {
+            return;
+        }
+
+        self.pairs_against(other, node, range, pairs);
+
+        let quads = a.children;
+        if quads != NONE {
+            for quad in quads..quads + 4 {
+                self.pairs_between(quad, other, range, pairs);
+            }
+        }
+    }
+}
+
+impl<T: Float + Coordinate, D> Quadtree<T, D> {
+    /// Entries inside a convex `polygon`, such as a rotated camera view.
+    pub fn query_polygon(&self, polygon: &ConvexPolygon<T>) -> Vec<&Entry<T, D>> {
+        self.query_shape(polygon).collect()
+    }
+
+    pub fn remove_nearest(&mut self, position: Vector2<T>) -> Option<Entry<T, D>> {
+        let (point, _) = self.search_nearest(position, 1, None).pop()?;
+
+        Some(self.remove_point(point))
+    }
+
+    pub fn nearest(&self, point: Vector2<T>) -> Option<Neighbor<'_, T, D>> {
+        self.k_nearest(point, 1).pop()
+    }
+
+    pub fn nearest_within(&self, point: Vector2<T>, max_distance: T) -> Option<Neighbor<'_, T, D>> {
+        self.k_nearest_within(point, 1, max_distance).pop()
+    }
+
+    pub fn k_nearest(&self, point: Vector2<T>, k: usize) -> Vec<Neighbor<'_, T, D>> {
+        self.neighbors(self.search_nearest(point, k, None))
+    }
+
+    pub fn k_nearest_within(
+        &self,
+        point: Vector2<T>,
+        k: usize,
+        max_distance: T,
+    ) -> Vec<Neighbor<'_, T, D>> {
+        self.neighbors(self.search_nearest(point, k, Some(max_distance)))
+    }
+
+    fn neighbors(&self, found: Vec<(u32, T)>) -> Vec<Neighbor<'_, T, D>> {
+        found
+            .into_iter()
+            .map(|(point, distance)| Neighbor {
+                point: &self.points[point as usize],
+                distance,
+            })
+            .collect()
+    }
+
+    // Best-first walk: quads and points share one queue keyed by their distance to `point`, so
+    // points come out of it already sorted and whole quads are only opened when they could win.
+    fn search_nearest(
+        &self,
+        point: Vector2<T>,
+        k: usize,
+        max_distance: Option<T>,
+    ) -> Vec<(u32, T)> {
+        let mut neighbors = Vec::new();
+        if k == 0 || self.is_empty() {
+            return neighbors;
+        }
+
+        let max_distance = max_distance.map(|distance| distance * distance);
+        let mut queue = BinaryHeap::new();
+        queue.push(Candidate {
+            distance: rect_distance_squared(self.bounds(), point),
+            item: CandidateItem::Quad(0),
+        });
+
+        while let Some(Candidate { distance, item }) = queue.pop() {
+            if max_distance.is_some_and(|max_distance| distance > max_distance) {
+                break;
+            }
+
+            match item {
+                CandidateItem::Point(child) => {
+                    neighbors.push((child, distance.sqrt()));
+
+                    if neighbors.len() == k {
+                        break;
+                    }
+                }
+                CandidateItem::Quad(quad) => {
+                    for child in self.node_points(quad) {
+                        queue.push(Candidate {
+                            distance: self.points[child as usize].position.distance_squared(point),
+                            item: CandidateItem::Point(child),
+                        });
+                    }
+
+                    let quads = self.nodes[quad as usize].children;
+                    if quads != NONE {
+                        for quad in quads..quads + 4 {
+                            queue.push(Candidate {
+                                distance: rect_distance_squared(
+                                    self.nodes[quad as usize].bounds,
+                                    point,
+                                ),
+                                item: CandidateItem::Quad(quad),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        neighbors
+    }
+
+    /// First entry within `tolerance` of the ray from `origin` along `direction`, at most
+    /// `max_distance` away. Quads are opened front to back and the walk ends at the first hit.
+    ///
+    /// `tolerance` is a required fourth argument rather than an option, as points have no size
+    /// for a ray to hit. Zero only finds entries exactly on the ray, and a zero `direction` only
+    /// finds entries within `tolerance` of `origin`.
+    pub fn raycast(
+        &self,
+        origin: Vector2<T>,
+        direction: Vector2<T>,
+        max_distance: T,
+        tolerance: T,
+    ) -> Option<RayHit<'_, T, D>> {
+        let hits = self.search_ray(origin, direction.normalize(), max_distance, tolerance, 1);
+
+        self.ray_hits(hits).pop()
+    }
+
+    /// Every entry within `tolerance` of the segment from `a` to `b`, sorted by how far along
+    /// the segment they are.
+    pub fn query_segment(
+        &self,
+        a: Vector2<T>,
+        b: Vector2<T>,
+        tolerance: T,
+    ) -> Vec<RayHit<'_, T, D>> {
+        let along = b - a;
+        let hits = self.search_ray(a, along.normalize(), along.length(), tolerance, usize::MAX);
+
+        self.ray_hits(hits)
+    }
+
+    fn ray_hits(&self, found: Vec<(u32, T)>) -> Vec<RayHit<'_, T, D>> {
+        found
+            .into_iter()
+            .map(|(point, distance)| RayHit {
+                point: &self.points[point as usize],
+                distance,
+            })
+            .collect()
+    }
+
+    // Same best-first walk as `search_nearest`, keyed by how far along the ray each quad is
+    // entered and each point is passed, which puts the hits in front to back order.
+    fn search_ray(
+        &self,
+        origin: Vector2<T>,
+        direction: Vector2<T>,
+        max_distance: T,
+        tolerance: T,
+        limit: usize,
+    ) -> Vec<(u32, T)> {
+        let mut hits = Vec::new();
+        if limit == 0 || self.is_empty() {
+            return hits;
+        }
+
+        let tolerance_squared = tolerance * tolerance;
+        let mut queue = BinaryHeap::new();
+        let push_quad = |queue: &mut BinaryHeap<_>, quad: u32| {
+            let bounds = self.nodes[quad as usize].bounds;
+            if let Some(distance) = ray_entry(bounds, origin, direction, max_distance, tolerance) {
+                queue.push(Candidate {
+                    distance,
+                    item: CandidateItem::Quad(quad),
+                });
+            }
+        };
+        push_quad(&mut queue, 0);
+
+        while let Some(Candidate { distance, item }) = queue.pop() {
+            match item {
+                CandidateItem::Point(child) => {
+                    hits.push((child, distance));
+
+                    if hits.len() == limit {
+                        break;
+                    }
+                }
+                CandidateItem::Quad(quad) => {
+                    for child in self.node_points(quad) {
+                        let offset = self.points[child as usize].position - origin;
+                        let along = offset.dot(direction).max(T::zero()).min(max_distance);
+
+                        if (offset - direction * along).length_squared() <= tolerance_squared {
+                            queue.push(Candidate {
+                                distance: along,
+                                item: CandidateItem::Point(child),
+                            });
+                        }
+                    }
+
+                    let quads = self.nodes[quad as usize].children;
+                    if quads != NONE {
+                        for quad in quads..quads + 4 {
+                            push_quad(&mut queue, quad);
+                        }
+                    }
+                }
+            }
+        }
+
+        hits
+    }
+}
+
+/// Read only view of a single quad, see `Quadtree::root`.
+#[derive(Debug)]
+pub struct NodeRef<'a, T, D = Option<usize>> {
+    tree: &'a Quadtree<T, D>,
+    node: u32,
+}
+
+impl<'a, T, D> Clone for NodeRef<'a, T, D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, D> Copy for NodeRef<'a, T, D> {}
+
+impl<'a, T: Coordinate, D> NodeRef<'a, T, D> {
+    fn raw(&self) -> &'a Node<Rect<T>> {
+        &self.tree.nodes[self.node as usize]
+    }
+
+    pub fn bounds(&self) -> Rect<T> {
+        self.raw().bounds
+    }
+
+    /// How many levels below the root this quad is.
+    pub fn depth(&self) -> usize {
+        self.raw().depth as usize
+    }
+
+    /// Number of points held by this quad itself, not counting its quads.
+    pub fn len(&self) -> usize {
+        self.raw().len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw().len == 0
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.raw().children == NONE
+    }
+
+    pub fn points(&self) -> NodePoints<'a, T, D> {
+        NodePoints {
+            tree: self.tree,
+            next: self.raw().first,
+        }
+    }
+
+    /// The NW, NE, SE and SW quads, `None` for leaves.
+    pub fn quads(&self) -> Option<[NodeRef<'a, T, D>; 4]> {
+        let quads = self.raw().children;
+        if quads == NONE {
+            return None;
+        }
+
+        let quad = |idx| NodeRef {
+            tree: self.tree,
+            node: quads + idx,
+        };
+
+        Some([quad(0), quad(1), quad(2), quad(3)])
+    }
+}
+
+/// The points held by a single quad, see `NodeRef::points`.
+#[derive(Debug, Clone)]
+pub struct NodePoints<'a, T, D = Option<usize>> {
+    tree: &'a Quadtree<T, D>,
+    next: u32,
+}
+
+impl<'a, T, D> Iterator for NodePoints<'a, T, D> {
+    type Item = &'a Entry<T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NONE {
+            return None;
+        }
+
+        let point = self.next as usize;
+        self.next = self.tree.links[point].next;
+
+        Some(&self.tree.points[point])
+    }
+}
+
+/// What `Quadtree::visit` should do after a quad has been visited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Continue,
+    SkipChildren,
+    Stop,
+}
+
+/// Custom traversal over the quads of a `Quadtree`, see `Quadtree::visit`.
+///
+/// Implemented for every `FnMut(NodeRef<T, D>) -> Visit` closure.
+pub trait Visitor<T, D> {
+    fn visit(&mut self, node: NodeRef<'_, T, D>) -> Visit;
+}
+
+impl<T, D, F> Visitor<T, D> for F
+where
+    F: FnMut(NodeRef<'_, T, D>) -> Visit,
+{
+    fn visit(&mut self, node: NodeRef<'_, T, D>) -> Visit {
+        self(node)
+    }
+}
+
+/// Iterator over every entry of a `Quadtree`, see `Quadtree::iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<'a, T, D = Option<usize>> {
+    points: slice::Iter<'a, Entry<T, D>>,
+}
+
+impl<'a, T, D> Iterator for Iter<'a, T, D> {
+    type Item = &'a Entry<T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.points.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.points.size_hint()
+    }
+}
+
+impl<'a, T, D> ExactSizeIterator for Iter<'a, T, D> {}
+
+/// Iterator over the positions and mutable data of a `Quadtree`, see `Quadtree::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, T, D = Option<usize>> {
+    points: slice::IterMut<'a, Entry<T, D>>,
+}
+
+impl<'a, T, D> Iterator for IterMut<'a, T, D> {
+    type Item = (&'a Vector2<T>, &'a mut D);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.points
+            .next()
+            .map(|point| (&point.position, &mut point.data))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.points.size_hint()
+    }
+}
+
+impl<'a, T, D> ExactSizeIterator for IterMut<'a, T, D> {}
+
+/// Owning iterator over every entry of a `Quadtree`.
+#[derive(Debug, Clone)]
+pub struct IntoIter<T, D = Option<usize>> {
+    points: vec::IntoIter<Entry<T, D>>,
+}
+
+impl<T, D> Iterator for IntoIter<T, D> {
+    type Item = Entry<T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.points.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.points.size_hint()
+    }
+}
+
+impl<T, D> ExactSizeIterator for IntoIter<T, D> {}
+
+impl<T, D> IntoIterator for Quadtree<T, D> {
+    type Item = Entry<T, D>;
+    type IntoIter = IntoIter<T, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            points: self.points.into_iter(),
+        }
+    }
+}
+
+impl<'a, T, D> IntoIterator for &'a Quadtree<T, D> {
+    type Item = &'a Entry<T, D>;
+    type IntoIter = Iter<'a, T, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter {
+            points: self.points.iter(),
+        }
+    }
+}
+
+impl<'a, T, D> IntoIterator for &'a mut Quadtree<T, D> {
+    type Item = (&'a Vector2<T>, &'a mut D);
+    type IntoIter = IterMut<'a, T, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IterMut {
+            points: self.points.iter_mut(),
+        }
+    }
+}
+
+/// Depth first walk over every quad of a `Quadtree`, see `Quadtree::nodes`.
+#[derive(Debug, Clone)]
+pub struct Nodes<'a, T, D = Option<usize>> {
+    tree: &'a Quadtree<T, D>,
+    stack: Vec<u32>,
+}
+
+impl<'a, T, D> Iterator for Nodes<'a, T, D> {
+    type Item = NodeRef<'a, T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+
+        let quads = self.tree.nodes[node as usize].children;
+        if quads != NONE {
+            self.stack.extend((quads..quads + 4).rev());
+        }
+
+        Some(NodeRef {
+            tree: self.tree,
+            node,
+        })
+    }
+}
+
+/// The undivided quads of a `Quadtree`, see `Quadtree::leaves`.
+#[derive(Debug, Clone)]
+pub struct Leaves<'a, T, D = Option<usize>> {
+    nodes: Nodes<'a, T, D>,
+}
+
+impl<'a, T, D> Iterator for Leaves<'a, T, D> {
+    type Item = NodeRef<'a, T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.nodes.tree;
+
+        self.nodes
+            .by_ref()
+            .find(|node| tree.nodes[node.node as usize].children == NONE)
+    }
+}
+
+/// An entry found by `Quadtree::raycast` or `Quadtree::query_segment`, `distance` being how
+/// far along the ray it is.
+#[derive(Debug, Copy, Clone)]
+pub struct RayHit<'a, T, D = Option<usize>> {
+    pub point: &'a Entry<T, D>,
+    pub distance: T,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Neighbor<'a, T, D = Option<usize>> {
+    pub point: &'a Entry<T, D>,
+    pub distance: T,
+}
+
+enum CandidateItem {
+    Quad(u32),
+    Point(u32),
+}
+
+// Orders the nearest search queue, reversed so `BinaryHeap` pops the closest candidate first.
+struct Candidate<T> {
+    distance: T,
+    item: CandidateItem,
+}
+
+impl<T: PartialOrd> PartialEq for Candidate<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: PartialOrd> Eq for Candidate<T> {}
+
+impl<T: PartialOrd> PartialOrd for Candidate<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialOrd> Ord for Candidate<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .partial_cmp(&self.distance)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Lazy query over a `Quadtree`, see `Quadtree::query_iter` and `Quadtree::query_shape`.
+///
+/// Only a small stack of pending quads is kept, the hits themselves are never collected. Quads
+/// the shape fully contains hand out all of their points without testing them.
+#[derive(Debug, Clone)]
+pub struct Query<'a, T, D = Option<usize>, S = Rect<T>> {
+    tree: &'a Quadtree<T, D>,
+    shape: S,
+    // Pending quads, along with whether they lie fully inside the shape.
+    stack: Vec<(u32, bool)>,
+    next: u32,
+    inside: bool,
+}
+
+impl<'a, T: Coordinate, D, S: Shape<T>> Query<'a, T, D, S> {
+    fn visit(&mut self, node: u32, mut inside: bool) {
+        let node = &self.tree.nodes[node as usize];
+        if !inside {
+            if !self.shape.overlaps(node.bounds) {
+                return;
+            }
+
+            inside = self.shape.contains_rect(node.bounds);
+        }
+
+        self.next = node.first;
+        self.inside = inside;
+
+        if node.children != NONE {
+            self.stack
+                .extend((node.children..node.children + 4).map(|quad| (quad, inside)));
+        }
+    }
+}
+
+impl<'a, T: Coordinate, D, S: Shape<T>> Iterator for Query<'a, T, D, S> {
+    type Item = &'a Entry<T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.next != NONE {
+                let point = self.next as usize;
+                self.next = self.tree.links[point].next;
+
+                let child = &self.tree.points[point];
+                if self.inside || self.shape.contains(child.position) {
+                    return Some(child);
+                }
+            }
+
+            let (node, inside) = self.stack.pop()?;
+            self.visit(node, inside);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{layout, points, sorted};
+    use crate::{Boundary, InsertError, PointIndex, UpdateError};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn from_points_matches_insert() {
+        let points = points(5000, 1);
+
+        // Bounds that halve exactly, and ones that leave rounding along the far edges.
+        for &bounds in &[
+            Rect::new(0.0, 0.0, 800.0, 600.0),
+            Rect::new(0.1, 0.3, 799.7, 599.9),
+        ] {
+            let mut inserted = Quadtree::new(bounds, 4).set_max_depth(12);
+            for point in &points {
+                let _ = inserted.insert(*point);
+            }
+            let loaded = Quadtree::new(bounds, 4)
+                .set_max_depth(12)
+                .load(points.iter().copied());
+
+            assert_eq!(loaded.len(), inserted.len());
+            assert_eq!(layout(&loaded), layout(&inserted));
+        }
+    }
+
+    #[test]
+    fn load_adds_to_existing_points() {
+        let bounds = Rect::new(0.0, 0.0, 800.0, 600.0);
+        let points = points(3000, 2);
+        let (first, second) = points.split_at(1000);
+
+        let mut inserted = Quadtree::new(bounds, 4);
+        for point in &points {
+            inserted.insert(*point).unwrap();
+        }
+        let loaded =
+            Quadtree::from_points(bounds, 4, first.iter().copied()).load(second.iter().copied());
+
+        assert_eq!(layout(&loaded), layout(&inserted));
+    }
+
+    #[test]
+    fn load_drops_what_does_not_fit() {
+        let mut points = points(100, 3);
+        points.push(Entry::new(Vector2::new(900.0, 10.0), Some(100)));
+        let tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points);
+
+        assert_eq!(tree.len(), 100);
+        assert!(tree.iter().all(|point| point.data != Some(100)));
+    }
+
+    #[test]
+    fn load_keeps_points_insert_would_reject() {
+        let position = Vector2::new(5, 2);
+        let points = (0..10).map(|idx| Entry::new(position, Some(idx)));
+        let tree: Quadtree<i32> = Quadtree::from_points(Rect::new(0, 0, 8, 8), 1, points);
+
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.query(Rect::new(5, 2, 1, 1)).len(), 10);
+        assert_eq!(tree.nodes().map(|node| node.depth()).max(), Some(3));
+
+        // Growing the root loads everything again, none of them may get lost on the way.
+        let mut tree = tree.set_auto_expand(true);
+        tree.insert(Entry::new(Vector2::new(20, 20), None)).unwrap();
+        assert_eq!(tree.len(), 11);
+        assert_eq!(tree.query(Rect::new(5, 2, 1, 1)).len(), 10);
+    }
+
+    #[test]
+    fn remove_merges_once_the_quads_fit() {
+        let mut tree = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 4);
+        let points = points(6, 4);
+        for point in &points {
+            tree.insert(*point).unwrap();
+        }
+        assert!(!tree.root().is_leaf());
+
+        // Five left is still more than the root takes on its own.
+        tree.remove(points[5].position, &points[5].data).unwrap();
+        assert!(!tree.root().is_leaf());
+
+        tree.remove(points[4].position, &points[4].data).unwrap();
+        assert!(tree.root().is_leaf());
+        assert_eq!(tree.nodes().count(), 1);
+        assert_eq!(layout(&tree), [(tree.bounds(), 0, vec![0, 1, 2, 3])]);
+    }
+
+    #[test]
+    fn remove_misses_leave_the_tree_alone() {
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(50, 5));
+        let before = layout(&tree);
+
+        assert!(tree.remove(Vector2::new(1.0, 1.0), &Some(0)).is_none());
+        assert!(tree
+            .remove(tree.iter().next().unwrap().position, &None)
+            .is_none());
+        assert!(tree.remove(Vector2::new(900.0, 1.0), &Some(0)).is_none());
+        assert_eq!(layout(&tree), before);
+    }
+
+    #[test]
+    fn removing_everything_leaves_one_node() {
+        let points = points(2000, 6);
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points.clone());
+        assert!(tree.nodes().count() > 100);
+
+        for point in points.iter().step_by(2) {
+            assert_eq!(
+                tree.remove(point.position, &point.data).unwrap().data,
+                point.data
+            );
+        }
+        while tree.remove_nearest(Vector2::new(400.0, 300.0)).is_some() {}
+
+        assert!(tree.is_empty());
+        assert!(tree.root().is_leaf());
+        assert_eq!(tree.nodes().count(), 1);
+    }
+
+    #[test]
+    fn coincident_points_exhaust_the_depth() {
+        // Every level keeps one of them, down to the one unit quads that cannot split again.
+        let position = Vector2::new(5, 2);
+        let mut tree: Quadtree<i32> = Quadtree::new(Rect::new(0, 0, 8, 8), 1);
+        for idx in 0..4 {
+            tree.insert(Entry::new(position, Some(idx))).unwrap();
+        }
+
+        assert_eq!(
+            tree.insert(Entry::new(position, Some(4))),
+            Err(InsertError::DepthExhausted)
+        );
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.nodes().map(|node| node.depth()).max(), Some(3));
+        assert_eq!(
+            tree.insert(Entry::new(Vector2::new(8, 0), None)),
+            Err(InsertError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn max_depth_lets_full_quads_grow() {
+        let position = Vector2::new(123.0, 456.0);
+        let mut tree = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 4).set_max_depth(3);
+        for idx in 0..100 {
+            tree.insert(Entry::new(position, Some(idx))).unwrap();
+        }
+
+        assert_eq!(tree.len(), 100);
+        assert!(tree.nodes().all(|node| node.depth() <= 3));
+
+        let deepest = tree
+            .leaves()
+            .find(|leaf| leaf.bounds().contains(position))
+            .unwrap();
+        assert_eq!(deepest.depth(), 3);
+        assert_eq!(deepest.len(), 100 - 3 * 4);
+        assert_eq!(tree.query(deepest.bounds()).len(), 100);
+    }
+
+    #[test]
+    fn closed_boundary_takes_the_far_edges() {
+        let bounds = Rect::new(0.0, 0.0, 800.0, 600.0);
+        let corners = [
+            Vector2::new(800.0, 0.0),
+            Vector2::new(0.0, 600.0),
+            Vector2::new(800.0, 600.0),
+        ];
+
+        let mut half_open = Quadtree::from_points(bounds, 2, points(200, 7));
+        for &corner in &corners {
+            assert_eq!(
+                half_open.insert(Entry::new(corner, None)),
+                Err(InsertError::OutOfBounds)
+            );
+        }
+
+        // Set after splitting, so the quads already along the far edges have to pick it up.
+        let mut closed = half_open.set_boundary(Boundary::Closed);
+        for &corner in &corners {
+            closed.insert(Entry::new(corner, None)).unwrap();
+        }
+        assert_eq!(closed.len(), 203);
+
+        for &corner in &corners {
+            let leaf = closed
+                .leaves()
+                .find(|leaf| leaf.points().any(|point| point.position == corner))
+                .unwrap();
+            let quad = leaf.bounds();
+            assert!(quad.right() == 800.0 || quad.bottom() == 600.0);
+            assert!(closed.remove(corner, &None).is_some());
+        }
+        assert!(closed
+            .insert(Entry::new(Vector2::new(800.1, 0.0), None))
+            .is_err());
+    }
+
+    #[test]
+    fn auto_expand_grows_towards_outside_points() {
+        let points = points(100, 8);
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points.clone());
+        let outside = Vector2::new(-30.0, 1250.0);
+        assert_eq!(
+            tree.insert(Entry::new(outside, Some(100))),
+            Err(InsertError::OutOfBounds)
+        );
+
+        let mut tree = tree.set_auto_expand(true);
+        tree.insert(Entry::new(outside, Some(100))).unwrap();
+
+        let bounds = tree.bounds();
+        assert!(bounds.contains(outside));
+        assert_eq!((bounds.width, bounds.height), (3200.0, 2400.0));
+        assert_eq!(tree.len(), 101);
+        for point in &points {
+            assert!(tree.remove(point.position, &point.data).is_some());
+        }
+
+        let nan = Vector2::new(f32::NAN, 0.0);
+        assert_eq!(
+            tree.insert(Entry::new(nan, None)),
+            Err(InsertError::OutOfBounds)
+        );
+        assert_eq!(tree.bounds(), bounds);
+    }
+
+    // The leaf holding the entry with `data`.
+    fn leaf_of(tree: &Quadtree<f32>, data: usize) -> Rect<f32> {
+        tree.nodes()
+            .find(|node| node.points().any(|point| point.data == Some(data)))
+            .unwrap()
+            .bounds()
+    }
+
+    #[test]
+    fn update_stays_local_inside_the_leaf() {
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 9));
+        let point = *tree.iter().find(|point| point.data == Some(42)).unwrap();
+        let leaf = leaf_of(&tree, 42);
+        let before = layout(&tree);
+
+        let moved = leaf.center();
+        tree.update(point.position, &point.data, moved).unwrap();
+
+        assert_eq!(layout(&tree), before);
+        assert_eq!(leaf_of(&tree, 42), leaf);
+        assert!(tree.remove(moved, &Some(42)).is_some());
+    }
+
+    #[test]
+    fn update_reinserts_across_quads() {
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 10));
+        let point = *tree.iter().find(|point| point.data == Some(42)).unwrap();
+        let leaf = leaf_of(&tree, 42);
+
+        let moved = Vector2::new(800.0, 600.0) - leaf.center();
+        tree.update(point.position, &point.data, moved).unwrap();
+
+        assert_eq!(tree.len(), 500);
+        assert!(leaf_of(&tree, 42).contains(moved));
+        assert!(tree.remove(point.position, &Some(42)).is_none());
+        assert!(tree.remove(moved, &Some(42)).is_some());
+
+        // Nothing of the move is left behind once the entry is gone again.
+        let mut rebuilt =
+            Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 10));
+        rebuilt.remove(point.position, &Some(42)).unwrap();
+        assert_eq!(layout(&tree), layout(&rebuilt));
+    }
+
+    #[test]
+    fn rejected_updates_leave_the_entry() {
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(100, 11));
+        let point = *tree.iter().find(|point| point.data == Some(7)).unwrap();
+
+        assert_eq!(
+            tree.update(point.position, &Some(100), Vector2::new(1.0, 1.0)),
+            Err(UpdateError::NotFound)
+        );
+        assert_eq!(
+            tree.update(point.position, &point.data, Vector2::new(-1.0, 1.0)),
+            Err(UpdateError::Rejected(InsertError::OutOfBounds))
+        );
+        assert_eq!(tree.len(), 100);
+        assert!(tree.remove(point.position, &point.data).is_some());
+    }
+
+    #[test]
+    fn update_merges_what_it_leaves_behind() {
+        let mut tree = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 1);
+        for (idx, &at) in [10.0, 20.0, 30.0].iter().enumerate() {
+            tree.insert(Entry::new(Vector2::new(at, at), Some(idx)))
+                .unwrap();
+        }
+        assert_eq!(tree.nodes().map(|node| node.depth()).max(), Some(2));
+
+        tree.update(
+            Vector2::new(30.0, 30.0),
+            &Some(2),
+            Vector2::new(700.0, 500.0),
+        )
+        .unwrap();
+
+        assert_eq!(tree.nodes().map(|node| node.depth()).max(), Some(1));
+        assert_eq!(tree.nodes().count(), 5);
+        assert_eq!(leaf_of(&tree, 2), Rect::new(400.0, 300.0, 400.0, 300.0));
+    }
+
+    #[test]
+    fn iterators_cover_every_entry() {
+        let points = points(300, 44);
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points.clone());
+        let all: Vec<usize> = (0..300).collect();
+
+        assert_eq!(sorted(tree.iter()), all);
+        assert_eq!(sorted(&tree), all);
+
+        for (position, data) in tree.iter_mut() {
+            assert_eq!(points[data.unwrap()].position, *position);
+            *data = data.map(|idx| idx + 1000);
+        }
+        assert_eq!(sorted(&tree), (1000..1300).collect::<Vec<_>>());
+
+        for (_, data) in &mut tree {
+            *data = data.map(|idx| idx - 1000);
+        }
+        assert_eq!(sorted(&tree), all);
+        assert_eq!(tree.validate(), Ok(()));
+
+        let owned: Vec<PointIndex<f32>> = tree.into_iter().collect();
+        assert_eq!(sorted(&owned), all);
+        assert!(owned
+            .iter()
+            .all(|point| point.position == points[point.data.unwrap()].position));
+    }
+
+    #[test]
+    fn nodes_and_leaves_follow_the_layout() {
+        let tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 45));
+        let expected = layout(&tree);
+        assert!(expected.iter().any(|&(_, depth, _)| depth > 2));
+
+        let nodes: Vec<_> = tree.nodes().map(quad_layout).collect();
+        assert_eq!(nodes, expected);
+
+        // In pre-order, a quad is a leaf unless the next one is deeper.
+        let leaves: Vec<_> = expected
+            .iter()
+            .enumerate()
+            .filter(|&(idx, (_, depth, _))| {
+                expected.get(idx + 1).is_none_or(|next| next.1 <= *depth)
+            })
+            .map(|(_, quad)| quad.clone())
+            .collect();
+        assert_eq!(tree.leaves().map(quad_layout).collect::<Vec<_>>(), leaves);
+        assert!(tree.leaves().all(|leaf| leaf.is_leaf()));
+    }
+
+    // What `layout` records for a quad, from its public view.
+    fn quad_layout(quad: NodeRef<'_, f32>) -> (Rect<f32>, usize, Vec<usize>) {
+        (quad.bounds(), quad.depth(), sorted(quad.points()))
+    }
+
+    // Records the quads it is shown, going no deeper than `max_depth`.
+    struct Shallow {
+        max_depth: usize,
+        seen: Vec<(Rect<f32>, usize, Vec<usize>)>,
+    }
+
+    impl Visitor<f32, Option<usize>> for Shallow {
+        fn visit(&mut self, quad: NodeRef<'_, f32>) -> Visit {
+            self.seen.push(quad_layout(quad));
+
+            if quad.depth() < self.max_depth {
+                Visit::Continue
+            } else {
+                Visit::SkipChildren
+            }
+        }
+    }
+
+    #[test]
+    fn visit_goes_through_the_quads_in_pre_order() {
+        let tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 41));
+        assert!(tree.nodes().map(|node| node.depth()).max() > Some(2));
+
+        let mut seen = Vec::new();
+        tree.visit(&mut |quad: NodeRef<'_, f32>| {
+            seen.push(quad_layout(quad));
+            Visit::Continue
+        });
+
+        assert_eq!(seen, layout(&tree));
+    }
+
+    #[test]
+    fn skip_children_prunes_below_the_quad() {
+        let tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 42));
+
+        for max_depth in 0..3 {
+            let mut shallow = Shallow {
+                max_depth,
+                seen: Vec::new(),
+            };
+            tree.visit(&mut shallow);
+
+            let mut expected = layout(&tree);
+            expected.retain(|&(_, depth, _)| depth <= max_depth);
+            assert_eq!(shallow.seen, expected);
+        }
+
+        // Skipping the first quad of the root leaves out everything below it.
+        let nw = tree.root().quads().unwrap()[0].bounds();
+        let mut seen = Vec::new();
+        tree.visit(&mut |quad: NodeRef<'_, f32>| {
+            seen.push(quad.bounds());
+            if quad.bounds() == nw {
+                Visit::SkipChildren
+            } else {
+                Visit::Continue
+            }
+        });
+        let expected: Vec<Rect<f32>> = layout(&tree)
+            .into_iter()
+            .map(|(bounds, _, _)| bounds)
+            .filter(|&bounds| bounds == nw || !nw.contains(bounds.center()))
+            .collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn stop_ends_the_walk() {
+        let tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points(500, 43));
+        let expected = layout(&tree);
+        assert!(expected.len() > 10);
+
+        let mut seen = Vec::new();
+        tree.visit(&mut |quad: NodeRef<'_, f32>| {
+            seen.push(quad_layout(quad));
+            if seen.len() == 10 {
+                Visit::Stop
+            } else {
+                Visit::Continue
+            }
+        });
+        assert_eq!(seen, expected[..10]);
+
+        let mut visited = 0;
+        tree.visit(&mut |_: NodeRef<'_, f32>| {
+            visited += 1;
+            Visit::Stop
+        });
+        assert_eq!(visited, 1);
+    }
+
+    fn brute_force(seed: u64) -> (Quadtree<f32>, Vec<PointIndex<f32>>, StdRng) {
+        let points = points(2000, seed);
+        let tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 4, points.clone());
+
+        (tree, points, StdRng::seed_from_u64(seed + 1))
+    }
+
+    #[test]
+    fn query_matches_brute_force() {
+        let (tree, points, mut rng) = brute_force(15);
+
+        for _ in 0..100 {
+            let range = Rect::new(
+                rng.gen_range(-100.0, 900.0),
+                rng.gen_range(-100.0, 700.0),
+                rng.gen_range(-300.0, 300.0),
+                rng.gen_range(-300.0, 300.0),
+            );
+            let expected = points.iter().filter(|p| range.contains(p.position));
+
+            assert_eq!(sorted(tree.query(range)), sorted(expected));
+            assert_eq!(sorted(tree.query_iter(range)), sorted(tree.query(range)));
+        }
+    }
+
+    #[test]
+    fn k_nearest_matches_brute_force() {
+        let (tree, points, mut rng) = brute_force(17);
+
+        for k in &[0, 1, 5, 40, 3000] {
+            let at = Vector2::new(rng.gen_range(-100.0, 900.0), rng.gen_range(-100.0, 700.0));
+            let mut expected: Vec<f32> = points.iter().map(|p| p.position.distance(at)).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(*k);
+
+            let found: Vec<f32> = tree.k_nearest(at, *k).iter().map(|n| n.distance).collect();
+            assert_eq!(found, expected);
+
+            // The radius is compared squared, like the search does.
+            let radius = rng.gen_range(0.0, 150.0);
+            let mut in_range: Vec<f32> = points
+                .iter()
+                .filter(|p| p.position.distance_squared(at) <= radius * radius)
+                .map(|p| p.position.distance(at))
+                .collect();
+            in_range.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            let within = tree.k_nearest_within(at, *k, radius);
+            let found: Vec<f32> = within.iter().map(|n| n.distance).collect();
+            assert_eq!(found, in_range[..in_range.len().min(*k)]);
+            assert_eq!(
+                tree.nearest_within(at, radius).map(|n| n.distance),
+                in_range.first().copied()
+            );
+        }
+    }
+
+    #[test]
+    fn collision_pairs_match_brute_force() {
+        let (tree, points, _) = brute_force(19);
+
+        for &radius in &[0.0, 3.0, 12.5] {
+            let mut expected = Vec::new();
+            for (idx, a) in points.iter().enumerate() {
+                for b in &points[idx + 1..] {
+                    if a.position.distance_squared(b.position) <= radius * radius {
+                        expected.push((a.data.unwrap(), b.data.unwrap()));
+                    }
+                }
+            }
+            expected.sort_unstable();
+
+            let mut found: Vec<(usize, usize)> = tree
+                .collision_pairs(radius)
+                .iter()
+                .map(|(a, b)| {
+                    let (a, b) = (a.data.unwrap(), b.data.unwrap());
+                    (a.min(b), a.max(b))
+                })
+                .collect();
+            found.sort_unstable();
+
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn query_segment_matches_brute_force() {
+        let (tree, points, mut rng) = brute_force(21);
+
+        for _ in 0..50 {
+            let a = Vector2::new(rng.gen_range(-100.0, 900.0), rng.gen_range(-100.0, 700.0));
+            let b = Vector2::new(rng.gen_range(-100.0, 900.0), rng.gen_range(-100.0, 700.0));
+            let tolerance = rng.gen_range(0.0, 20.0);
+
+            // Same closest point on the segment the query uses, so rounding agrees.
+            let (direction, length) = ((b - a).normalize(), (b - a).length());
+            let expected = points.iter().filter(|p| {
+                let offset = p.position - a;
+                let along = offset.dot(direction).max(0.0).min(length);
+                (offset - direction * along).length_squared() <= tolerance * tolerance
+            });
+
+            let hits = tree.query_segment(a, b, tolerance);
+            assert!(hits.windows(2).all(|w| w[0].distance <= w[1].distance));
+            assert_eq!(sorted(hits.iter().map(|hit| hit.point)), sorted(expected));
+        }
+    }
+
+    // Three points along a horizontal line, each in a quad of its own.
+    fn ray_targets() -> Quadtree<f32> {
+        let mut tree = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 1);
+        for (idx, &x) in [100.0, 400.0, 600.0].iter().enumerate() {
+            tree.insert(Entry::new(Vector2::new(x, 300.0), Some(idx)))
+                .unwrap();
+        }
+
+        tree
+    }
+
+    fn hit(hit: Option<RayHit<'_, f32>>) -> Option<(usize, f32)> {
+        hit.map(|hit| (hit.point.data.unwrap(), hit.distance))
+    }
+
+    #[test]
+    fn raycast_stops_at_the_first_hit() {
+        let tree = ray_targets();
+        let (origin, direction) = (Vector2::new(0.0, 300.0), Vector2::new(1.0, 0.0));
+
+        assert_eq!(
+            hit(tree.raycast(origin, direction, 1000.0, 0.0)),
+            Some((0, 100.0))
+        );
+        assert_eq!(hit(tree.raycast(origin, direction, 50.0, 0.0)), None);
+        assert_eq!(
+            hit(tree.raycast(Vector2::new(200.0, 305.0), direction, 1000.0, 5.0)),
+            Some((1, 200.0))
+        );
+        assert_eq!(
+            hit(tree.raycast(Vector2::new(200.0, 305.0), direction, 1000.0, 4.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn raycast_matches_brute_force() {
+        let (tree, points, mut rng) = brute_force(25);
+
+        for _ in 0..50 {
+            let origin = Vector2::new(rng.gen_range(-100.0, 900.0), rng.gen_range(-100.0, 700.0));
+            let direction = Vector2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+            let tolerance = rng.gen_range(0.0, 20.0);
+
+            // Same closest point on the ray the search uses, so rounding agrees.
+            let unit = direction.normalize();
+            let nearest = points
+                .iter()
+                .filter_map(|p| {
+                    let offset = p.position - origin;
+                    let along = offset.dot(unit).clamp(0.0, 500.0);
+                    let off_ray = (offset - unit * along).length_squared();
+                    Some(along).filter(|_| off_ray <= tolerance * tolerance)
+                })
+                .fold(None, |nearest: Option<f32>, along| {
+                    Some(nearest.map_or(along, |nearest| nearest.min(along)))
+                });
+
+            let found = tree.raycast(origin, direction, 500.0, tolerance);
+            assert_eq!(found.map(|hit| hit.distance), nearest);
+        }
+    }
+
+    #[test]
+    fn raycast_follows_negative_directions() {
+        let tree = ray_targets();
+        let origin = Vector2::new(500.0, 300.0);
+
+        assert_eq!(
+            hit(tree.raycast(origin, Vector2::new(-1.0, 0.0), 1000.0, 0.0)),
+            Some((1, 100.0))
+        );
+        assert_eq!(
+            hit(tree.raycast(origin, Vector2::new(-3.0, 0.0), 50.0, 0.0)),
+            None
+        );
+        assert_eq!(
+            hit(tree.raycast(
+                Vector2::new(100.0, 500.0),
+                Vector2::new(0.0, -2.0),
+                1000.0,
+                0.0
+            )),
+            Some((0, 200.0))
+        );
+    }
+
+    #[test]
+    fn raycast_enters_the_root_from_outside() {
+        let tree = ray_targets();
+
+        assert_eq!(
+            hit(tree.raycast(
+                Vector2::new(-100.0, 300.0),
+                Vector2::new(1.0, 0.0),
+                1000.0,
+                0.0
+            )),
+            Some((0, 200.0))
+        );
+        assert_eq!(
+            hit(tree.raycast(
+                Vector2::new(900.0, 300.0),
+                Vector2::new(-1.0, 0.0),
+                1000.0,
+                0.0
+            )),
+            Some((2, 300.0))
+        );
+        assert_eq!(
+            hit(tree.raycast(
+                Vector2::new(-100.0, 300.0),
+                Vector2::new(-1.0, 0.0),
+                1000.0,
+                0.0
+            )),
+            None
+        );
+        assert_eq!(
+            hit(tree.raycast(
+                Vector2::new(-100.0, 700.0),
+                Vector2::new(1.0, 0.0),
+                1000.0,
+                0.0
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn zero_direction_only_finds_the_origin() {
+        let tree = ray_targets();
+        let zero = Vector2::new(0.0, 0.0);
+
+        assert_eq!(
+            hit(tree.raycast(Vector2::new(403.0, 304.0), zero, 1000.0, 5.0)),
+            Some((1, 0.0))
+        );
+        assert_eq!(
+            hit(tree.raycast(Vector2::new(403.0, 304.0), zero, 1000.0, 4.0)),
+            None
+        );
+        assert_eq!(
+            hit(tree.raycast(Vector2::new(250.0, 300.0), zero, 1000.0, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn query_polygon_matches_brute_force() {
+        let (tree, points, mut rng) = brute_force(23);
+
+        for _ in 0..50 {
+            let center = Vector2::new(rng.gen_range(0.0, 800.0), rng.gen_range(0.0, 600.0));
+            let (angle, width, height): (f32, f32, f32) = (
+                rng.gen_range(0.0, 6.3),
+                rng.gen_range(1.0, 300.0),
+                rng.gen_range(1.0, 300.0),
+            );
+            let (along, across) = (
+                Vector2::new(angle.cos(), angle.sin()),
+                Vector2::new(-angle.sin(), angle.cos()),
+            );
+            let polygon = ConvexPolygon::new(vec![
+                center - along * width - across * height,
+                center + along * width - across * height,
+                center + along * width + across * height,
+                center - along * width + across * height,
+            ]);
+            let expected = points.iter().filter(|p| polygon.contains(p.position));
+
+            assert_eq!(sorted(tree.query_polygon(&polygon)), sorted(expected));
+        }
+    }
+}
@@ -0,0 +1,553 @@
+use crate::tree::Bounds;
+use crate::{Coordinate, Float, InsertError, Rect};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RectQuadtree<T, D = Option<usize>> {
+    pub bounds: Rect<T>,
+    pub capacity: usize,
+    max_capacity: usize,
+    depth: usize,
+    max_depth: Option<usize>,
+    // Scale of the loose bounds, `None` for a strict quadtree.
+    looseness: Option<T>,
+    pub items: Vec<RectEntry<T, D>>,
+    pub quads: Option<Vec<RectQuadtree<T, D>>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RectEntry<T, D> {
+    pub bounds: Rect<T>,
+    pub data: D,
+}
+
+impl<T, D> RectEntry<T, D> {
+    pub fn new(bounds: Rect<T>, data: D) -> Self {
+        RectEntry { bounds, data }
+    }
+}
+
+pub type RectIndex<T> = RectEntry<T, Option<usize>>;
+
+/// Two items reported together by `RectQuadtree::collision_pairs`.
+pub type RectPair<'a, T, D> = (&'a RectEntry<T, D>, &'a RectEntry<T, D>);
+
+impl<T: Coordinate, D> RectQuadtree<T, D> {
+    pub fn new(bounds: Rect<T>, capacity: usize) -> Self {
+        RectQuadtree {
+            bounds,
+            capacity,
+            max_capacity: capacity,
+            depth: 0,
+            max_depth: None,
+            looseness: None,
+            items: Vec::with_capacity(capacity),
+            quads: None,
+        }
+    }
+
+    pub fn set_bounds(mut self, bounds: Rect<T>) -> Self {
+        self.bounds = bounds;
+
+        self
+    }
+
+    pub fn set_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self.items = Vec::with_capacity(capacity);
+
+        self
+    }
+
+    /// Quads this many levels below the root stop dividing and keep every item they are given.
+    pub fn set_max_depth(mut self, max_depth: usize) -> Self {
+        self.apply_max_depth(Some(max_depth));
+
+        self
+    }
+
+    fn apply_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+
+        for quad in self.quads.iter_mut().flatten() {
+            quad.apply_max_depth(max_depth);
+        }
+    }
+
+    fn at_max_depth(&self) -> bool {
+        self.max_depth
+            .is_some_and(|max_depth| self.depth >= max_depth)
+    }
+
+    fn apply_looseness(&mut self, looseness: Option<T>) {
+        self.looseness = looseness;
+
+        for quad in self.quads.iter_mut().flatten() {
+            quad.apply_looseness(looseness);
+        }
+    }
+
+    /// The area items stored in this quad are kept within, `bounds` scaled by the looseness.
+    pub fn loose_bounds(&self) -> Rect<T> {
+        let looseness = match self.looseness {
+            Some(looseness) => looseness,
+            None => return self.bounds,
+        };
+
+        let center = self.bounds.center();
+        let bounds = self.bounds.normalize();
+        let (width, height) = (bounds.width * looseness, bounds.height * looseness);
+
+        Rect::new(
+            center.x - width.half(),
+            center.y - height.half(),
+            width,
+            height,
+        )
+    }
+
+    // Items sink into the quad under their center for as long as it fully contains them,
+    // anything straddling a split stays behind in the node that was split.
+    pub fn insert(&mut self, item: RectEntry<T, D>) -> Result<(), InsertError> {
+        self.insert_entry(item).map_err(|(error, _)| error)
+    }
+
+    // `insert`, handing the item back when it is turned away.
+    fn insert_entry(
+        &mut self,
+        item: RectEntry<T, D>,
+    ) -> Result<(), (InsertError, RectEntry<T, D>)> {
+        if !self.loose_bounds().contains_rect(item.bounds) {
+            return Err((InsertError::OutOfBounds, item));
+        }
+
+        if self.quads.is_none() {
+            if self.items.len() < self.capacity || self.at_max_depth() {
+                self.items.push(item);
+                return Ok(());
+            }
+
+            if !self.bounds.can_divide() {
+                return Err((InsertError::DepthExhausted, item));
+            }
+
+            self.divide();
+        }
+
+        self.place(item)
+    }
+
+    fn place(&mut self, item: RectEntry<T, D>) -> Result<(), (InsertError, RectEntry<T, D>)> {
+        let center = item.bounds.center();
+        let quad = self.quads.iter_mut().flatten().find(|quad| {
+            quad.bounds.contains(center) && quad.loose_bounds().contains_rect(item.bounds)
+        });
+
+        match quad {
+            Some(quad) => quad.insert_entry(item),
+            None => {
+                self.items.push(item);
+                Ok(())
+            }
+        }
+    }
+
+    fn divide(&mut self) {
+        if self.quads.is_some() {
+            return;
+        }
+
+        let (max_capacity, depth, max_depth) = (self.max_capacity, self.depth + 1, self.max_depth);
+        let looseness = self.looseness;
+        self.quads = Some(
+            self.bounds
+                .quarters()
+                .iter()
+                .map(|&bounds| RectQuadtree {
+                    depth,
+                    max_depth,
+                    looseness,
+                    ..RectQuadtree::new(bounds, max_capacity)
+                })
+                .collect(),
+        );
+
+        // Items a full quad that cannot split turns away stay up here instead.
+        for item in std::mem::take(&mut self.items) {
+            if let Err((_, item)) = self.place(item) {
+                self.items.push(item);
+            }
+        }
+    }
+
+    /// Items intersecting `range`, touching edges included.
+    pub fn query(&self, range: Rect<T>) -> Vec<&RectEntry<T, D>> {
+        self.query_iter(range).collect()
+    }
+
+    pub fn query_iter(&self, range: Rect<T>) -> RectQuery<'_, T, D> {
+        let mut query = RectQuery {
+            range,
+            stack: Vec::new(),
+            items: [].iter(),
+        };
+        query.visit(self);
+
+        query
+    }
+
+    /// Every unordered pair of intersecting items, each reported once. Touching counts, so items
+    /// with no width or height still collide.
+    pub fn collision_pairs(&self) -> Vec<RectPair<'_, T, D>> {
+        let mut pairs = Vec::new();
+        self.pairs_within(&mut pairs);
+
+        pairs
+    }
+
+    fn pairs_within<'a>(&'a self, pairs: &mut Vec<RectPair<'a, T, D>>) {
+        for (idx, a) in self.items.iter().enumerate() {
+            for b in self.items[idx + 1..].iter() {
+                if a.bounds.intersects(b.bounds) {
+                    pairs.push((a, b));
+                }
+            }
+        }
+
+        if let Some(quads) = self.quads.as_ref() {
+            for (idx, quad) in quads.iter().enumerate() {
+                quad.pairs_against(&self.items, pairs);
+                quad.pairs_within(pairs);
+
+                for other in quads[idx + 1..].iter() {
+                    quad.pairs_between(other, pairs);
+                }
+            }
+        }
+    }
+
+    // Pairs every item in this subtree with every item in the disjoint subtree `other`. Strict
+    // siblings only share an edge, which only items lying right along it can touch across.
+    fn pairs_between<'a>(
+        &'a self,
+        other: &'a RectQuadtree<T, D>,
+        pairs: &mut Vec<RectPair<'a, T, D>>,
+    ) {
+        if !self.loose_bounds().intersects(other.loose_bounds()) {
+            return;
+        }
+
+        other.pairs_against(&self.items, pairs);
+
+        for quad in self.quads.iter().flatten() {
+            quad.pairs_between(other, pairs);
+        }
+    }
+
+    // Pairs every item in this subtree with every item in `others`.
+    fn pairs_against<'a>(
+        &'a self,
+        others: &'a [RectEntry<T, D>],
+        pairs: &mut Vec<RectPair<'a, T, D>>,
+    ) {
+        if others
+            .iter()
+            .all(|a| !a.bounds.intersects(self.loose_bounds()))
+        {
+            return;
+        }
+
+        for a in others.iter() {
+            for b in self.items.iter() {
+                if a.bounds.intersects(b.bounds) {
+                    pairs.push((a, b));
+                }
+            }
+        }
+
+        for quad in self.quads.iter().flatten() {
+            quad.pairs_against(others, pairs);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let quads = self
+            .quads
+            .as_ref()
+            .map_or(0, |quads| quads.iter().map(|quad| quad.len()).sum());
+
+        self.items.len() + quads
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.quads.iter().flatten().all(|quad| quad.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.quads = None;
+    }
+}
+
+impl<T: Float + Coordinate, D> RectQuadtree<T, D> {
+    /// Turns this into a loose quadtree, where every quad accepts items reaching up to
+    /// `looseness` times its size around its center. Values below one are treated as one.
+    pub fn set_looseness(mut self, looseness: T) -> Self {
+        self.apply_looseness(Some(looseness).filter(|&looseness| looseness > T::one()));
+
+        self
+    }
+}
+
+/// Lazy intersection query over a `RectQuadtree`, see `RectQuadtree::query_iter`.
+#[derive(Debug, Clone)]
+pub struct RectQuery<'a, T, D = Option<usize>> {
+    range: Rect<T>,
+    stack: Vec<&'a RectQuadtree<T, D>>,
+    items: std::slice::Iter<'a, RectEntry<T, D>>,
+}
+
+impl<'a, T: Coordinate, D> RectQuery<'a, T, D> {
+    fn visit(&mut self, quad: &'a RectQuadtree<T, D>) {
+        if !quad.loose_bounds().intersects(self.range) {
+            return;
+        }
+
+        self.items = quad.items.iter();
+
+        if let Some(quads) = quad.quads.as_ref() {
+            self.stack.extend(quads.iter());
+        }
+    }
+}
+
+impl<'a, T: Coordinate, D> Iterator for RectQuery<'a, T, D> {
+    type Item = &'a RectEntry<T, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for item in &mut self.items {
+                if item.bounds.intersects(self.range) {
+                    return Some(item);
+                }
+            }
+
+            let quad = self.stack.pop()?;
+            self.visit(quad);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Small random rects in a 100 by 100 area, some of them without any width or height.
+    fn items(count: usize, seed: u64) -> Vec<RectIndex<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        (0..count)
+            .map(|idx| {
+                let size = |rng: &mut StdRng| match rng.gen_range(0, 4) {
+                    0 => 0.0,
+                    _ => rng.gen_range(0.5, 8.0),
+                };
+                let (width, height) = (size(&mut rng), size(&mut rng));
+                let bounds = Rect::new(
+                    rng.gen_range(0.0, 100.0 - width),
+                    rng.gen_range(0.0, 100.0 - height),
+                    width,
+                    height,
+                );
+                RectEntry::new(bounds, Some(idx))
+            })
+            .collect()
+    }
+
+    fn sorted(items: Vec<&RectIndex<f32>>) -> Vec<usize> {
+        let mut indices: Vec<usize> = items.iter().map(|item| item.data.unwrap()).collect();
+        indices.sort_unstable();
+
+        indices
+    }
+
+    // Checks `query` and `collision_pairs` against testing every item and every pair.
+    fn matches_brute_force(tree: RectQuadtree<f32>, seed: u64) {
+        let items = items(800, seed);
+        let mut tree = tree;
+        for item in &items {
+            tree.insert(*item).unwrap();
+        }
+        assert_eq!(tree.len(), items.len());
+
+        let mut rng = StdRng::seed_from_u64(seed + 1);
+        for _ in 0..100 {
+            let range = Rect::new(
+                rng.gen_range(-10.0, 100.0),
+                rng.gen_range(-10.0, 100.0),
+                rng.gen_range(0.0, 40.0),
+                rng.gen_range(0.0, 40.0),
+            );
+            let expected = items.iter().filter(|item| item.bounds.intersects(range));
+
+            assert_eq!(sorted(tree.query(range)), sorted(expected.collect()));
+        }
+
+        let mut expected = Vec::new();
+        for (idx, a) in items.iter().enumerate() {
+            for b in &items[idx + 1..] {
+                if a.bounds.intersects(b.bounds) {
+                    expected.push((a.data.unwrap(), b.data.unwrap()));
+                }
+            }
+        }
+        expected.sort_unstable();
+
+        let mut found: Vec<(usize, usize)> = tree
+            .collision_pairs()
+            .iter()
+            .map(|(a, b)| {
+                let (a, b) = (a.data.unwrap(), b.data.unwrap());
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        found.sort_unstable();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn straddling_items_stay_in_the_split_quad() {
+        let mut tree = RectQuadtree::new(Rect::new(0.0, 0.0, 100.0, 100.0), 2);
+        let nw = RectEntry::new(Rect::new(10.0, 10.0, 5.0, 5.0), Some(0));
+        let straddling = RectEntry::new(Rect::new(45.0, 45.0, 10.0, 10.0), Some(1));
+        let se = RectEntry::new(Rect::new(80.0, 70.0, 5.0, 5.0), Some(2));
+        for &item in &[nw, straddling, se] {
+            tree.insert(item).unwrap();
+        }
+
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.items.len(), 1);
+        assert_eq!(tree.items[0].data, Some(1));
+        let quads = tree.quads.as_ref().unwrap();
+        assert_eq!(quads[0].items[0].data, Some(0));
+        assert_eq!(quads[2].items[0].data, Some(2));
+
+        assert_eq!(sorted(tree.query(Rect::new(0.0, 0.0, 50.0, 50.0))), [0, 1]);
+        assert_eq!(
+            sorted(tree.query(Rect::new(55.0, 55.0, 45.0, 45.0))),
+            [1, 2]
+        );
+        assert_eq!(
+            tree.insert(RectEntry::new(Rect::new(95.0, 0.0, 10.0, 1.0), None)),
+            Err(InsertError::OutOfBounds)
+        );
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert!(tree.quads.is_none());
+    }
+
+    #[test]
+    fn flat_items_are_found() {
+        let mut tree = RectQuadtree::new(Rect::new(0.0, 0.0, 50.0, 50.0), 4);
+        let wall = RectEntry::new(Rect::new(10.0, 10.0, 0.0, 20.0), Some(0));
+        let crossing = RectEntry::new(Rect::new(5.0, 15.0, 10.0, 2.0), Some(1));
+        let touching = RectEntry::new(Rect::new(10.0, 40.0, 5.0, 5.0), Some(2));
+        for &item in &[wall, crossing, touching] {
+            tree.insert(item).unwrap();
+        }
+
+        assert_eq!(
+            sorted(tree.query(Rect::new(0.0, 0.0, 50.0, 50.0))),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            sorted(tree.query(Rect::new(10.0, 0.0, 0.0, 50.0))),
+            [0, 1, 2]
+        );
+        assert!(tree.query(Rect::new(11.0, 0.0, 1.0, 10.0)).is_empty());
+
+        let pairs = tree.collision_pairs();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(sorted(vec![pairs[0].0, pairs[0].1]), [0, 1]);
+    }
+
+    #[test]
+    fn full_quads_too_small_to_split() {
+        let mut tree: RectQuadtree<i32> = RectQuadtree::new(Rect::new(0, 0, 2, 2), 1);
+        let item = RectEntry::new(Rect::new(0, 0, 1, 1), None);
+        tree.insert(item).unwrap();
+
+        assert_eq!(tree.insert(item), Err(InsertError::DepthExhausted));
+        assert_eq!(tree.len(), 1);
+
+        let mut tree = tree.set_max_depth(1);
+        tree.insert(item).unwrap();
+        tree.insert(item).unwrap();
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn items_touching_across_quads_collide() {
+        let mut tree = RectQuadtree::new(Rect::new(0.0, 0.0, 100.0, 100.0), 1);
+        let west = RectEntry::new(Rect::new(40.0, 10.0, 10.0, 5.0), Some(0));
+        let east = RectEntry::new(Rect::new(50.0, 12.0, 5.0, 5.0), Some(1));
+        tree.insert(west).unwrap();
+        tree.insert(east).unwrap();
+
+        let quads = tree.quads.as_ref().unwrap();
+        assert_eq!((quads[0].len(), quads[1].len()), (1, 1));
+        let pairs = tree.collision_pairs();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(sorted(vec![pairs[0].0, pairs[0].1]), [0, 1]);
+    }
+
+    #[test]
+    fn query_and_pairs_match_brute_force() {
+        matches_brute_force(RectQuadtree::new(Rect::new(0.0, 0.0, 100.0, 100.0), 4), 1);
+    }
+
+    #[test]
+    fn loose_bounds_scale_around_the_center() {
+        let tree: RectQuadtree<f32> = RectQuadtree::new(Rect::new(0.0, 0.0, 100.0, 60.0), 4);
+        assert_eq!(tree.loose_bounds(), tree.bounds);
+
+        let tree = tree.set_looseness(2.0);
+        assert_eq!(tree.loose_bounds(), Rect::new(-50.0, -30.0, 200.0, 120.0));
+
+        let tree = tree.set_looseness(0.5);
+        assert_eq!(tree.loose_bounds(), tree.bounds);
+    }
+
+    #[test]
+    fn loose_quads_take_items_across_their_edges() {
+        let mut tree = RectQuadtree::new(Rect::new(0.0, 0.0, 100.0, 100.0), 1).set_looseness(2.0);
+        let first = RectEntry::new(Rect::new(10.0, 10.0, 5.0, 5.0), Some(0));
+        let straddling = RectEntry::new(Rect::new(45.0, 40.0, 8.0, 8.0), Some(1));
+        tree.insert(first).unwrap();
+        tree.insert(straddling).unwrap();
+
+        // The center of the straddling item is in the NW quad, whose loose bounds reach past it.
+        assert!(tree.items.is_empty());
+        let quads = tree.quads.as_ref().unwrap();
+        assert_eq!(
+            quads[0].loose_bounds(),
+            Rect::new(-25.0, -25.0, 100.0, 100.0)
+        );
+        assert_eq!(quads[0].len(), 2);
+        assert_eq!(sorted(tree.query(Rect::new(52.0, 47.0, 1.0, 1.0))), [1]);
+    }
+
+    #[test]
+    fn loose_query_and_pairs_match_brute_force() {
+        for &looseness in &[1.5, 2.0, 4.0] {
+            let tree = RectQuadtree::new(Rect::new(0.0, 0.0, 100.0, 100.0), 4);
+            matches_brute_force(tree.set_looseness(looseness), 2);
+        }
+    }
+}
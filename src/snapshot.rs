@@ -0,0 +1,520 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::tree::{Bounds, Link, Node, Positioned, SpatialTree};
+use crate::{Aabb, Aabb3, Entry, Entry3, Item, Rect, Vector2, Vector3};
+
+pub(crate) const MAGIC: [u8; 4] = *b"QTRE";
+pub(crate) const VERSION: u16 = 1;
+
+/// Little-endian encoding of the coordinates and data stored in a snapshot, see
+/// `Quadtree::write_to` and `Quadtree::read_from`.
+pub trait Encode: Sized {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError>;
+}
+
+macro_rules! encode_le {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    reader.read_exact(&mut bytes)?;
+
+                    Ok(<$ty>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+encode_le!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+// Pointer sized integers always take 8 bytes, so snapshots move between platforms.
+impl Encode for usize {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u64).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        usize::try_from(u64::decode(reader)?).map_err(|_| SnapshotError::Corrupt("usize overflow"))
+    }
+}
+
+impl Encode for isize {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as i64).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        isize::try_from(i64::decode(reader)?).map_err(|_| SnapshotError::Corrupt("isize overflow"))
+    }
+}
+
+impl Encode for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Corrupt("invalid bool")),
+        }
+    }
+}
+
+impl Encode for () {
+    fn encode<W: Write>(&self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn decode<R: Read>(_reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(())
+    }
+}
+
+impl<E: Encode> Encode for Option<E> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Some(value) => {
+                true.encode(writer)?;
+                value.encode(writer)
+            }
+            None => false.encode(writer),
+        }
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        if bool::decode(reader)? {
+            Ok(Some(E::decode(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.iter().try_for_each(|value| value.encode(writer))
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        let values = (0..N)
+            .map(|_| T::decode(reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        <[T; N]>::try_from(values).map_err(|_| SnapshotError::Corrupt("array length mismatch"))
+    }
+}
+
+/// Why `Quadtree::read_from` could not load a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The input does not start with the snapshot magic bytes.
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The input decoded, but does not describe a valid tree.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "snapshot could not be read: {}", error),
+            SnapshotError::BadMagic => write!(f, "input is not a quadtree snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Corrupt(reason) => write!(f, "corrupt snapshot: {}", reason),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl<T: Encode> Encode for Vector2<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.x.encode(writer)?;
+        self.y.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Vector2::new(T::decode(reader)?, T::decode(reader)?))
+    }
+}
+
+impl<T: Encode> Encode for Rect<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.left.encode(writer)?;
+        self.top.encode(writer)?;
+        self.width.encode(writer)?;
+        self.height.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Rect {
+            left: T::decode(reader)?,
+            top: T::decode(reader)?,
+            width: T::decode(reader)?,
+            height: T::decode(reader)?,
+        })
+    }
+}
+
+impl<T: Encode, D: Encode> Encode for Entry<T, D> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.position.encode(writer)?;
+        self.data.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Entry::new(Vector2::decode(reader)?, D::decode(reader)?))
+    }
+}
+
+impl<T: Encode> Encode for Vector3<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.x.encode(writer)?;
+        self.y.encode(writer)?;
+        self.z.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Vector3::new(
+            T::decode(reader)?,
+            T::decode(reader)?,
+            T::decode(reader)?,
+        ))
+    }
+}
+
+impl<T: Encode> Encode for Aabb3<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.min.encode(writer)?;
+        self.size.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Aabb3 {
+            min: Vector3::decode(reader)?,
+            size: Vector3::decode(reader)?,
+        })
+    }
+}
+
+impl<T: Encode, D: Encode> Encode for Entry3<T, D> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.position.encode(writer)?;
+        self.data.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Entry3::new(Vector3::decode(reader)?, D::decode(reader)?))
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for Aabb<T, N> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.min.encode(writer)?;
+        self.size.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Aabb {
+            min: <[T; N]>::decode(reader)?,
+            size: <[T; N]>::decode(reader)?,
+        })
+    }
+}
+
+impl<T: Encode, D: Encode, const N: usize> Encode for Item<T, D, N> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.position.encode(writer)?;
+        self.data.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Ok(Item::new(<[T; N]>::decode(reader)?, D::decode(reader)?))
+    }
+}
+
+/// Binary snapshots: a header with the settings, root bounds and table sizes, followed by the
+/// node, free block and point tables exactly as they are laid out in memory, all little-endian.
+impl<B, E> SpatialTree<B, E>
+where
+    B: Bounds + Encode,
+    E: Positioned<Point = B::Point> + Encode,
+{
+    /// Writes to `writer` one value at a time, so wrap files in a `BufWriter`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let writer = &mut writer;
+
+        writer.write_all(&MAGIC)?;
+        VERSION.encode(writer)?;
+
+        self.bounds().encode(writer)?;
+        self.capacity.encode(writer)?;
+        self.max_capacity.encode(writer)?;
+        self.max_depth.encode(writer)?;
+        self.auto_expand.encode(writer)?;
+        (self.nodes.len() as u32).encode(writer)?;
+        (self.free.len() as u32).encode(writer)?;
+        (self.points.len() as u32).encode(writer)?;
+
+        for node in &self.nodes {
+            node.bounds.encode(writer)?;
+            node.depth.encode(writer)?;
+            node.closed.encode(writer)?;
+            node.parent.encode(writer)?;
+            node.children.encode(writer)?;
+            node.first.encode(writer)?;
+            node.len.encode(writer)?;
+        }
+
+        for first in &self.free {
+            first.encode(writer)?;
+        }
+
+        for (point, link) in self.points.iter().zip(&self.links) {
+            point.encode(writer)?;
+            link.node.encode(writer)?;
+            link.next.encode(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a snapshot written by `write_to`, checking that it describes a well formed tree
+    /// before handing it out.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, SnapshotError> {
+        let reader = &mut reader;
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let version = u16::decode(reader)?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let bounds = B::decode(reader)?;
+        let capacity = usize::decode(reader)?;
+        let max_capacity = usize::decode(reader)?;
+        let max_depth = Option::decode(reader)?;
+        let auto_expand = bool::decode(reader)?;
+        let node_count = u32::decode(reader)?;
+        let free_count = u32::decode(reader)?;
+        let point_count = u32::decode(reader)?;
+
+        // Grown as the tables are read, so a corrupt count cannot reserve huge buffers upfront.
+        let mut nodes = Vec::new();
+        for _ in 0..node_count {
+            let bounds = B::decode(reader)?;
+            let depth = u32::decode(reader)?;
+            let closed = u8::decode(reader)?;
+            if u32::from(closed) >> B::DIMENSIONS != 0 {
+                return Err(SnapshotError::Corrupt("invalid node edges"));
+            }
+
+            nodes.push(Node {
+                bounds,
+                depth,
+                closed,
+                parent: u32::decode(reader)?,
+                children: u32::decode(reader)?,
+                first: u32::decode(reader)?,
+                len: u32::decode(reader)?,
+            });
+        }
+
+        let mut free = Vec::new();
+        for _ in 0..free_count {
+            free.push(u32::decode(reader)?);
+        }
+
+        let (mut points, mut links) = (Vec::new(), Vec::new());
+        for _ in 0..point_count {
+            points.push(E::decode(reader)?);
+            links.push(Link {
+                node: u32::decode(reader)?,
+                next: u32::decode(reader)?,
+            });
+        }
+
+        if nodes.first().map(|root| root.bounds) != Some(bounds) {
+            return Err(SnapshotError::Corrupt("root does not match the header"));
+        }
+
+        let tree = SpatialTree {
+            capacity,
+            max_capacity,
+            max_depth,
+            auto_expand,
+            nodes,
+            free,
+            points,
+            links,
+        };
+        tree.validate().map_err(SnapshotError::Corrupt)?;
+
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boundary, Orthtree, PointIndex, Quadtree};
+
+    // Bytes per node in the node table: bounds, depth, edges, then four indices.
+    const NODE: usize = 16 + 4 + 1 + 4 * 4;
+
+    fn bytes<B, E>(tree: &SpatialTree<B, E>) -> Vec<u8>
+    where
+        B: Bounds + Encode,
+        E: Positioned<Point = B::Point> + Encode,
+    {
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes).unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn round_trips_eight_closed_axes() {
+        let mut tree: Orthtree<i32, 8, u8> = Orthtree::new(Aabb::new([0; 8], [16; 8]), 1)
+            .set_boundary(Boundary::Closed)
+            .set_children();
+        for value in 0..5 {
+            tree.insert(Item::new([value as i32 * 4; 8], value))
+                .unwrap();
+        }
+
+        let written = bytes(&tree);
+        let read = Orthtree::<i32, 8, u8>::read_from(&written[..]).unwrap();
+
+        assert_eq!(bytes(&read), written);
+        assert_eq!(read.len(), 5);
+    }
+
+    fn sample() -> Quadtree<f32> {
+        let mut tree = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 1).set_quads();
+        for (idx, &(x, y)) in [(10.0, 10.0), (20.0, 30.0), (500.0, 400.0)]
+            .iter()
+            .enumerate()
+        {
+            tree.insert(PointIndex::with_index(Vector2::new(x, y), Some(idx)))
+                .unwrap();
+        }
+
+        tree
+    }
+
+    fn corrupt(bytes: &[u8]) -> Option<&'static str> {
+        match Quadtree::<f32>::read_from(bytes) {
+            Err(SnapshotError::Corrupt(reason)) => Some(reason),
+            _ => None,
+        }
+    }
+
+    // Where the node table starts, the header being all that is left of a tree with one node.
+    fn header() -> usize {
+        bytes(&Quadtree::<f32>::new(Rect::new(0.0, 0.0, 800.0, 600.0), 1)).len() - NODE
+    }
+
+    #[test]
+    fn round_trips() {
+        let written = bytes(&sample());
+        let read = Quadtree::<f32>::read_from(&written[..]).unwrap();
+
+        assert_eq!(bytes(&read), written);
+        assert_eq!(read.len(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut written = bytes(&sample());
+        written[0] = b'X';
+
+        assert!(matches!(
+            Quadtree::<f32>::read_from(&written[..]),
+            Err(SnapshotError::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut written = bytes(&sample());
+        written[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(matches!(
+            Quadtree::<f32>::read_from(&written[..]),
+            Err(SnapshotError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let written = bytes(&sample());
+
+        for len in 0..written.len() {
+            assert!(
+                Quadtree::<f32>::read_from(&written[..len]).is_err(),
+                "read {} of {} bytes",
+                len,
+                written.len()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_children_not_matching_the_split() {
+        // The NW child is the second node, shrinking it still leaves its points inside.
+        let mut written = bytes(&sample());
+        let nw = header() + NODE;
+        written[nw + 8..nw + 12].copy_from_slice(&100.0f32.to_le_bytes());
+        written[nw + 12..nw + 16].copy_from_slice(&100.0f32.to_le_bytes());
+
+        assert_eq!(
+            corrupt(&written),
+            Some("node does not cover its part of the parent")
+        );
+    }
+
+    #[test]
+    fn rejects_children_with_the_wrong_edges() {
+        let mut written = bytes(&sample());
+        written[header() + NODE + 20] = 0b01;
+
+        assert_eq!(
+            corrupt(&written),
+            Some("node does not cover its part of the parent")
+        );
+    }
+}
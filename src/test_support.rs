@@ -0,0 +1,60 @@
+// Helpers shared by the unit tests of every module.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::tree::{Bounds, Positioned, SpatialTree, NONE};
+use crate::{Entry, PointIndex, Vector2};
+
+// Random points inside an 800 by 600 rect at the origin, indexed in order.
+pub(crate) fn points(count: usize, seed: u64) -> Vec<PointIndex<f32>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..count)
+        .map(|idx| {
+            let position = Vector2::new(rng.gen_range(0.0, 800.0), rng.gen_range(0.0, 600.0));
+            Entry::new(position, Some(idx))
+        })
+        .collect()
+}
+
+// The indices of `entries`, in ascending order.
+pub(crate) fn sorted<'a, E>(entries: impl IntoIterator<Item = &'a E>) -> Vec<usize>
+where
+    E: Positioned<Data = Option<usize>> + 'a,
+{
+    let mut indices: Vec<usize> = entries.into_iter().map(|e| e.data().unwrap()).collect();
+    indices.sort_unstable();
+
+    indices
+}
+
+// Every node with its depth and the indices of its own points, parents before their children.
+pub(crate) fn layout<B, E>(tree: &SpatialTree<B, E>) -> Vec<(B, usize, Vec<usize>)>
+where
+    B: Bounds,
+    E: Positioned<Point = B::Point, Data = Option<usize>>,
+{
+    fn walk<B, E>(tree: &SpatialTree<B, E>, node: u32, layout: &mut Vec<(B, usize, Vec<usize>)>)
+    where
+        B: Bounds,
+        E: Positioned<Point = B::Point, Data = Option<usize>>,
+    {
+        let node_ref = &tree.nodes[node as usize];
+        let points = tree
+            .node_points(node)
+            .map(|point| &tree.points[point as usize]);
+        layout.push((node_ref.bounds, node_ref.depth as usize, sorted(points)));
+
+        if node_ref.children != NONE {
+            for child in node_ref.children..node_ref.children + B::CHILDREN as u32 {
+                walk(tree, child, layout);
+            }
+        }
+    }
+
+    let mut layout = Vec::new();
+    walk(tree, 0, &mut layout);
+
+    layout
+}
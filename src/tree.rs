@@ -0,0 +1,908 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+use crate::{Boundary, InsertError, UpdateError};
+
+// Stands in for a missing node or point index.
+pub(crate) const NONE: u32 = u32::MAX;
+
+/// Axis aligned box a `SpatialTree` can split up, `Rect` for the `Quadtree`, `Aabb3` for the
+/// `Octree` and `Aabb` for any other number of dimensions.
+///
+/// Far edges are passed around as masks with one bit per axis, set when points lying exactly on
+/// the far edge along that axis belong to the box.
+pub trait Bounds: Copy + PartialEq {
+    type Point: Copy + PartialEq;
+
+    const DIMENSIONS: usize;
+    /// Boxes made by a single split.
+    const CHILDREN: usize = 1 << Self::DIMENSIONS;
+
+    /// One of the `CHILDREN` boxes splitting `self`, numbered in the order the tree stores them.
+    fn child(&self, idx: usize) -> Self;
+
+    /// Which far edges the child at `child` shares with the box it was split from.
+    fn far_edges(child: usize) -> u8;
+
+    fn holds(&self, point: Self::Point, closed: u8) -> bool;
+
+    /// Whether splitting still leaves room on both sides along at least one axis.
+    fn can_divide(&self) -> bool;
+
+    /// Index of the child of `self` likely to hold `point`, the tree double checks it with
+    /// `holds`.
+    fn guess_child(&self, point: Self::Point) -> usize;
+
+    /// Whether `guess_child` always names the child holding a point `self` holds, with no rounding
+    /// left along the far edges of the split. Bulk loads then take it without double checking,
+    /// `false` is always safe.
+    fn exact_split(&self) -> bool {
+        false
+    }
+
+    /// Doubles `self` towards `point` until it is held, or gives up if it never will be.
+    fn grow(&self, point: Self::Point, closed: u8) -> Option<Self>;
+}
+
+/// Something stored in a `SpatialTree`, found again through its position and data.
+pub trait Positioned {
+    type Point: Copy + PartialEq;
+    type Data;
+
+    fn position(&self) -> Self::Point;
+    fn set_position(&mut self, position: Self::Point);
+    fn data(&self) -> &Self::Data;
+}
+
+/// The point tree behind `Quadtree`, `Octree` and `Orthtree`, keeping all of its nodes in one `Vec`
+/// and all of its points in another.
+///
+/// Nodes refer to each other by index, the children of a split sit next to each other and the
+/// points of a node are threaded through the point buffer as a linked list.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "RawTree<B, E>",
+        bound(deserialize = "B: Bounds + Deserialize<'de>, \
+            E: Positioned<Point = B::Point> + Deserialize<'de>")
+    )
+)]
+pub struct SpatialTree<B, E> {
+    pub(crate) capacity: usize,
+    pub(crate) max_capacity: usize,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) auto_expand: bool,
+    pub(crate) nodes: Vec<Node<B>>,
+    // First index of every block of children given up by `merge`, ready to be reused.
+    pub(crate) free: Vec<u32>,
+    pub(crate) points: Vec<E>,
+    pub(crate) links: Vec<Link>,
+}
+
+// The fields of a `SpatialTree` as serde reads them, only turned into a tree once `validate`
+// agrees, the same as `read_from` does for snapshots.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawTree<B, E> {
+    capacity: usize,
+    max_capacity: usize,
+    max_depth: Option<usize>,
+    auto_expand: bool,
+    nodes: Vec<Node<B>>,
+    free: Vec<u32>,
+    points: Vec<E>,
+    links: Vec<Link>,
+}
+
+#[cfg(feature = "serde")]
+impl<B: Bounds, E: Positioned<Point = B::Point>> TryFrom<RawTree<B, E>> for SpatialTree<B, E> {
+    type Error = &'static str;
+
+    fn try_from(raw: RawTree<B, E>) -> Result<Self, &'static str> {
+        let tree = SpatialTree {
+            capacity: raw.capacity,
+            max_capacity: raw.max_capacity,
+            max_depth: raw.max_depth,
+            auto_expand: raw.auto_expand,
+            nodes: raw.nodes,
+            free: raw.free,
+            points: raw.points,
+            links: raw.links,
+        };
+        tree.validate()?;
+
+        Ok(tree)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Node<B> {
+    pub(crate) bounds: B,
+    pub(crate) depth: u32,
+    // Far edges taking in the points lying on them, see `Bounds`.
+    pub(crate) closed: u8,
+    pub(crate) parent: u32,
+    // First of the children, `NONE` for leaves.
+    pub(crate) children: u32,
+    // Head of this node's list of points.
+    pub(crate) first: u32,
+    pub(crate) len: u32,
+}
+
+impl<B> Node<B> {
+    fn new(bounds: B, depth: u32, parent: u32) -> Self {
+        Node {
+            bounds,
+            depth,
+            closed: 0,
+            parent,
+            children: NONE,
+            first: NONE,
+            len: 0,
+        }
+    }
+}
+
+// Which node a point belongs to and the point after it in that node's list.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct Link {
+    pub(crate) node: u32,
+    pub(crate) next: u32,
+}
+
+impl<B: Bounds, E: Positioned<Point = B::Point>> SpatialTree<B, E> {
+    pub fn new(bounds: B, capacity: usize) -> Self {
+        SpatialTree {
+            capacity,
+            max_capacity: capacity,
+            max_depth: None,
+            auto_expand: false,
+            nodes: vec![Node::new(bounds, 0, NONE)],
+            free: Vec::new(),
+            points: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+
+    pub fn from_points<I>(bounds: B, capacity: usize, points: I) -> Self
+    where
+        I: IntoIterator<Item = E>,
+    {
+        SpatialTree::new(bounds, capacity).load(points)
+    }
+
+    pub fn set_bounds(mut self, bounds: B) -> Self {
+        self.nodes[0].bounds = bounds;
+
+        self
+    }
+
+    pub fn set_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+
+        self
+    }
+
+    /// Nodes this many levels below the root stop dividing and keep every point they are given.
+    pub fn set_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+
+        self
+    }
+
+    pub fn set_boundary(mut self, boundary: Boundary) -> Self {
+        let closed = match boundary {
+            Boundary::HalfOpen => 0,
+            Boundary::Closed => ((1 << B::DIMENSIONS) - 1) as u8,
+        };
+        self.apply_boundary(0, closed);
+
+        self
+    }
+
+    fn apply_boundary(&mut self, node: u32, closed: u8) {
+        let node = &mut self.nodes[node as usize];
+        node.closed = closed;
+
+        let children = node.children;
+        if children != NONE {
+            for (idx, child) in (children..children + B::CHILDREN as u32).enumerate() {
+                self.apply_boundary(child, closed & B::far_edges(idx));
+            }
+        }
+    }
+
+    /// Instead of rejecting points outside of `bounds`, keep doubling the root towards them.
+    pub fn set_auto_expand(mut self, auto_expand: bool) -> Self {
+        self.auto_expand = auto_expand;
+
+        self
+    }
+
+    pub fn bounds(&self) -> B {
+        self.nodes[0].bounds
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn capacity_of(&self, node: u32) -> usize {
+        if node == 0 {
+            self.capacity
+        } else {
+            self.max_capacity
+        }
+    }
+
+    fn at_max_depth(&self, node: u32) -> bool {
+        let depth = self.nodes[node as usize].depth as usize;
+
+        self.max_depth.is_some_and(|max_depth| depth >= max_depth)
+    }
+
+    pub(crate) fn holds(&self, node: u32, position: B::Point) -> bool {
+        let node = &self.nodes[node as usize];
+
+        node.bounds.holds(position, node.closed)
+    }
+
+    // Which child of `node` takes `position`, matching the first one to hold it but without
+    // testing all of them in the common case.
+    pub(crate) fn child_of(&self, node: u32, position: B::Point) -> Option<u32> {
+        let node = &self.nodes[node as usize];
+        let first = node.children;
+
+        let guess = first + node.bounds.guess_child(position) as u32;
+        if self.holds(guess, position) {
+            return Some(guess);
+        }
+
+        (first..first + B::CHILDREN as u32).find(|&child| self.holds(child, position))
+    }
+
+    // Moves everything over to a root with the new `bounds`.
+    fn rebuild(&mut self, bounds: B) {
+        let entries = std::mem::take(&mut self.points);
+
+        self.reset(bounds);
+        self.bulk_insert(0, entries);
+    }
+
+    fn reset(&mut self, bounds: B) {
+        self.nodes.truncate(1);
+        self.free.clear();
+        self.points.clear();
+        self.links.clear();
+
+        let root = &mut self.nodes[0];
+        root.bounds = bounds;
+        root.children = NONE;
+        root.first = NONE;
+        root.len = 0;
+    }
+
+    /// Inserts every point in one top-down pass, ending up with the same nodes as calling
+    /// `insert` for each of them in order. Points outside of `bounds` are dropped unless auto
+    /// expanding, while points `insert` would reject with `DepthExhausted` are kept in the full
+    /// node.
+    pub fn load<I>(mut self, points: I) -> Self
+    where
+        I: IntoIterator<Item = E>,
+    {
+        let mut points: Vec<E> = points.into_iter().collect();
+
+        if self.auto_expand {
+            let closed = self.nodes[0].closed;
+            let bounds = points.iter().fold(self.bounds(), |bounds, point| {
+                if bounds.holds(point.position(), closed) {
+                    return bounds;
+                }
+
+                bounds.grow(point.position(), closed).unwrap_or(bounds)
+            });
+
+            if bounds != self.bounds() {
+                self.rebuild(bounds);
+            }
+        }
+
+        points.retain(|point| self.holds(0, point.position()));
+        self.bulk_insert(0, points);
+
+        self
+    }
+
+    pub fn insert(&mut self, location: E) -> Result<(), InsertError> {
+        self.insert_entry(location).map_err(|(error, _)| error)
+    }
+
+    // `insert`, handing the entry back when it is turned away.
+    fn insert_entry(&mut self, location: E) -> Result<(), (InsertError, E)> {
+        if !self.holds(0, location.position()) {
+            if !self.auto_expand {
+                return Err((InsertError::OutOfBounds, location));
+            }
+
+            match self
+                .bounds()
+                .grow(location.position(), self.nodes[0].closed)
+            {
+                Some(bounds) => self.rebuild(bounds),
+                None => return Err((InsertError::OutOfBounds, location)),
+            }
+        }
+
+        let mut node = 0;
+        loop {
+            if self.nodes[node as usize].children == NONE {
+                let len = self.nodes[node as usize].len as usize;
+                if len < self.capacity_of(node) || self.at_max_depth(node) {
+                    self.push_point(node, location);
+                    return Ok(());
+                }
+
+                if !self.nodes[node as usize].bounds.can_divide() {
+                    return Err((InsertError::DepthExhausted, location));
+                }
+
+                self.divide(node);
+            }
+
+            match self.child_of(node, location.position()) {
+                Some(child) => node = child,
+                None => {
+                    // Rounding left a sliver along the far edges that none of the children cover.
+                    self.push_point(node, location);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Moves an entry to `new_position`, in place when it stays inside the node holding it and
+    /// through `remove` and `insert` otherwise. A rejected move leaves the entry where it was.
+    pub fn update(
+        &mut self,
+        old_position: B::Point,
+        data: &E::Data,
+        new_position: B::Point,
+    ) -> Result<(), UpdateError>
+    where
+        E::Data: PartialEq,
+    {
+        let point = self
+            .find(old_position, &|child| child.data() == data)
+            .ok_or(UpdateError::NotFound)?;
+
+        let node = self.links[point as usize].node;
+        if self.holds(node, new_position) {
+            self.points[point as usize].set_position(new_position);
+            return Ok(());
+        }
+
+        if !self.holds(0, new_position) && !self.auto_expand {
+            return Err(UpdateError::Rejected(InsertError::OutOfBounds));
+        }
+
+        let mut entry = self.take_point(point);
+        self.merge_up(node);
+        entry.set_position(new_position);
+
+        self.insert_entry(entry).map_err(|(error, mut entry)| {
+            entry.set_position(old_position);
+            let _ = self.insert_entry(entry);
+
+            UpdateError::Rejected(error)
+        })
+    }
+
+    // Index of the first point at `position` that `matches`.
+    fn find(&self, position: B::Point, matches: &dyn Fn(&E) -> bool) -> Option<u32> {
+        if !self.holds(0, position) {
+            return None;
+        }
+
+        let mut node = 0;
+        loop {
+            let found = self.node_points(node).find(|&point| {
+                let child = &self.points[point as usize];
+                child.position() == position && matches(child)
+            });
+
+            if found.is_some() {
+                return found;
+            }
+
+            if self.nodes[node as usize].children == NONE {
+                return None;
+            }
+
+            node = self.child_of(node, position)?;
+        }
+    }
+
+    // Expects every point to already be inside the bounds of `node`.
+    fn bulk_insert(&mut self, node: u32, points: Vec<E>) {
+        let mut items: Vec<(B::Point, u32)> = points
+            .iter()
+            .enumerate()
+            .map(|(idx, point)| (point.position(), idx as u32))
+            .collect();
+        let mut scratch = items.clone();
+        let mut keys = vec![0; items.len()];
+        let mut dest = vec![NONE; items.len()];
+        self.place(node, &mut items, &mut scratch, &mut keys, &mut dest);
+
+        for (point, node) in points.into_iter().zip(dest) {
+            self.push_point(node, point);
+        }
+    }
+
+    // Works out the node every point in `items` goes to, by position and index into the points
+    // being loaded, and notes it down in `dest`.
+    //
+    // Nothing is inserted yet, the items are partitioned in place one level at a time instead, a
+    // counting sort through `scratch` on the child each of them falls into.
+    fn place(
+        &mut self,
+        node: u32,
+        items: &mut [(B::Point, u32)],
+        scratch: &mut [(B::Point, u32)],
+        keys: &mut [u32],
+        dest: &mut [u32],
+    ) {
+        let mut kept = 0;
+        if self.nodes[node as usize].children == NONE {
+            let len = self.nodes[node as usize].len as usize;
+            let free = self.capacity_of(node).saturating_sub(len);
+
+            // Where `insert` would turn points away for exhausting the depth, they all stay in the
+            // full node instead, so loading never loses points that are in bounds.
+            if self.at_max_depth(node)
+                || items.len() <= free
+                || !self.nodes[node as usize].bounds.can_divide()
+            {
+                kept = items.len();
+            } else {
+                // Like `insert`, the node keeps the points that still fit and the rest go below.
+                kept = free;
+                self.divide(node);
+            }
+        }
+
+        let (stay, items) = items.split_at_mut(kept);
+        for &(_, point) in stay.iter() {
+            dest[point as usize] = node;
+        }
+        if items.is_empty() || self.nodes[node as usize].children == NONE {
+            return;
+        }
+
+        let first = self.nodes[node as usize].children;
+        let bounds = self.nodes[node as usize].bounds;
+        let exact = bounds.exact_split();
+        let keys = &mut keys[..items.len()];
+        let mut ends = vec![0; B::CHILDREN + 1];
+        for (key, &(position, _)) in keys.iter_mut().zip(items.iter()) {
+            *key = if exact {
+                bounds.guess_child(position) as u32 + 1
+            } else {
+                self.child_of(node, position)
+                    .map_or(0, |child| child - first + 1)
+            };
+            ends[*key as usize] += 1;
+        }
+
+        for idx in 1..ends.len() {
+            ends[idx] += ends[idx - 1];
+        }
+
+        // Filling each bucket from its end keeps the points in the order they were given.
+        let scratch = &mut scratch[..items.len()];
+        for (&key, &item) in keys.iter().zip(items.iter()).rev() {
+            ends[key as usize] -= 1;
+            scratch[ends[key as usize]] = item;
+        }
+        items.copy_from_slice(scratch);
+
+        // Points no child covers, from rounding along the far edges, stay behind.
+        ends.push(items.len());
+        for &(_, point) in &items[..ends[1]] {
+            dest[point as usize] = node;
+        }
+
+        for (child, bucket) in (first..).zip(ends[1..].windows(2)) {
+            if bucket[0] < bucket[1] {
+                let items = &mut items[bucket[0]..bucket[1]];
+                self.place(child, items, scratch, keys, dest);
+            }
+        }
+    }
+
+    pub(crate) fn divide(&mut self, node: u32) {
+        if self.nodes[node as usize].children != NONE {
+            return;
+        }
+
+        let parent = &self.nodes[node as usize];
+        let (bounds, depth, closed) = (parent.bounds, parent.depth + 1, parent.closed);
+        let child = |idx| Node {
+            closed: closed & B::far_edges(idx),
+            ..Node::new(bounds.child(idx), depth, node)
+        };
+
+        // Children go straight into a merged block when there is one, or onto the end.
+        let first = match self.free.pop() {
+            Some(first) => {
+                let start = first as usize;
+                for (idx, slot) in self.nodes[start..start + B::CHILDREN]
+                    .iter_mut()
+                    .enumerate()
+                {
+                    *slot = child(idx);
+                }
+                first
+            }
+            None => {
+                let first = self.nodes.len() as u32;
+                self.nodes.extend((0..B::CHILDREN).map(child));
+                first
+            }
+        };
+
+        self.nodes[node as usize].children = first;
+    }
+
+    fn push_point(&mut self, node: u32, entry: E) {
+        let point = self.points.len() as u32;
+        let node_ref = &mut self.nodes[node as usize];
+
+        self.points.push(entry);
+        self.links.push(Link {
+            node,
+            next: node_ref.first,
+        });
+
+        node_ref.first = point;
+        node_ref.len += 1;
+    }
+
+    // Unlinks a point from its node and swaps the last point of the buffer into its slot.
+    fn take_point(&mut self, point: u32) -> E {
+        let node = self.links[point as usize].node;
+        let next = self.links[point as usize].next;
+        self.relink(node, point, next);
+        self.nodes[node as usize].len -= 1;
+
+        let last = self.points.len() as u32 - 1;
+        let entry = self.points.swap_remove(point as usize);
+        self.links.swap_remove(point as usize);
+
+        if point != last {
+            let node = self.links[point as usize].node;
+            self.relink(node, last, point);
+        }
+
+        entry
+    }
+
+    // Points whatever refers to `from` in the list of `node` at `to` instead.
+    fn relink(&mut self, node: u32, from: u32, to: u32) {
+        if self.nodes[node as usize].first == from {
+            self.nodes[node as usize].first = to;
+            return;
+        }
+
+        let mut point = self.nodes[node as usize].first;
+        while self.links[point as usize].next != from {
+            point = self.links[point as usize].next;
+        }
+        self.links[point as usize].next = to;
+    }
+
+    pub(crate) fn node_points(&self, node: u32) -> impl Iterator<Item = u32> + '_ {
+        self.chain(self.nodes[node as usize].first)
+    }
+
+    // Indices of `point` and of every point after it in its node's list.
+    pub(crate) fn chain(&self, mut point: u32) -> impl Iterator<Item = u32> + '_ {
+        std::iter::from_fn(move || {
+            if point == NONE {
+                return None;
+            }
+
+            let current = point;
+            point = self.links[point as usize].next;
+            Some(current)
+        })
+    }
+
+    // Every entry `contains` accepts, only looking into the nodes `overlaps` accepts.
+    pub(crate) fn collect_where(
+        &self,
+        overlaps: impl Fn(&B) -> bool,
+        contains: impl Fn(&E) -> bool,
+    ) -> Vec<&E> {
+        let mut found = Vec::new();
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let node_ref = &self.nodes[node as usize];
+            if !overlaps(&node_ref.bounds) {
+                continue;
+            }
+
+            found.extend(
+                self.node_points(node)
+                    .map(|point| &self.points[point as usize])
+                    .filter(|&point| contains(point)),
+            );
+
+            if node_ref.children != NONE {
+                stack.extend(node_ref.children..node_ref.children + B::CHILDREN as u32);
+            }
+        }
+
+        found
+    }
+
+    pub fn remove(&mut self, position: B::Point, data: &E::Data) -> Option<E>
+    where
+        E::Data: PartialEq,
+    {
+        let point = self.find(position, &|child| child.data() == data)?;
+
+        Some(self.remove_point(point))
+    }
+
+    pub(crate) fn remove_point(&mut self, point: u32) -> E {
+        let node = self.links[point as usize].node;
+        let entry = self.take_point(point);
+        self.merge_up(node);
+
+        entry
+    }
+
+    // Merges from `node` towards the root for as long as children keep collapsing.
+    fn merge_up(&mut self, mut node: u32) {
+        loop {
+            if self.nodes[node as usize].children != NONE {
+                self.merge(node);
+
+                if self.nodes[node as usize].children != NONE {
+                    return;
+                }
+            }
+
+            if node == 0 {
+                return;
+            }
+            node = self.nodes[node as usize].parent;
+        }
+    }
+
+    // Folds the children back into `node` once everything left fits in its own points.
+    fn merge(&mut self, node: u32) {
+        let first = self.nodes[node as usize].children;
+        let children = &self.nodes[first as usize..first as usize + B::CHILDREN];
+
+        if children.iter().any(|child| child.children != NONE) {
+            return;
+        }
+
+        let total = self.nodes[node as usize].len as usize
+            + children
+                .iter()
+                .map(|child| child.len as usize)
+                .sum::<usize>();
+        if total > self.capacity_of(node) {
+            return;
+        }
+
+        for child in first..first + B::CHILDREN as u32 {
+            let mut point = self.nodes[child as usize].first;
+            while point != NONE {
+                let next = self.links[point as usize].next;
+                self.links[point as usize] = Link {
+                    node,
+                    next: self.nodes[node as usize].first,
+                };
+                self.nodes[node as usize].first = point;
+                point = next;
+            }
+
+            self.nodes[node as usize].len += self.nodes[child as usize].len;
+        }
+
+        self.nodes[node as usize].children = NONE;
+        self.free.push(first);
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.reset(self.bounds());
+    }
+
+    // Checks every index, so the rest of the tree can trust them without bounds checks failing.
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if self.links.len() != self.points.len() {
+            return Err("point tables of different lengths");
+        }
+
+        let root = self.nodes.first().ok_or("no root node")?;
+        if root.parent != NONE || root.depth != 0 {
+            return Err("invalid root");
+        }
+
+        // Every other node belongs to exactly one block of children, either in use or free.
+        let mut owned = vec![false; self.nodes.len()];
+        owned[0] = true;
+        let mut claim = |first: u32| {
+            let first = first as usize;
+            if first == 0 || first > owned.len().saturating_sub(B::CHILDREN) {
+                return Err("node out of range");
+            }
+
+            for owned in &mut owned[first..first + B::CHILDREN] {
+                if *owned {
+                    return Err("node shared between parents");
+                }
+                *owned = true;
+            }
+
+            Ok(())
+        };
+
+        let mut live = vec![0];
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let parent = &self.nodes[node as usize];
+            if parent.children == NONE {
+                continue;
+            }
+
+            claim(parent.children)?;
+            for (idx, child) in (parent.children..parent.children + B::CHILDREN as u32).enumerate()
+            {
+                let child_ref = &self.nodes[child as usize];
+                if child_ref.parent != node || child_ref.depth.checked_sub(1) != Some(parent.depth)
+                {
+                    return Err("node does not match its parent");
+                }
+                if child_ref.bounds != parent.bounds.child(idx)
+                    || child_ref.closed != parent.closed & B::far_edges(idx)
+                {
+                    return Err("node does not cover its part of the parent");
+                }
+
+                live.push(child);
+                stack.push(child);
+            }
+        }
+
+        for &first in &self.free {
+            claim(first)?;
+        }
+
+        if owned.contains(&false) {
+            return Err("unreachable node");
+        }
+
+        let mut seen = vec![false; self.points.len()];
+        for node in live {
+            let node_ref = &self.nodes[node as usize];
+
+            let mut len = 0;
+            let mut point = node_ref.first;
+            while point != NONE {
+                let seen = seen.get_mut(point as usize).ok_or("point out of range")?;
+                if *seen {
+                    return Err("point listed twice");
+                }
+                *seen = true;
+
+                let (entry, link) = (&self.points[point as usize], self.links[point as usize]);
+                if link.node != node {
+                    return Err("point listed under the wrong node");
+                }
+                if !node_ref.bounds.holds(entry.position(), node_ref.closed) {
+                    return Err("point outside of its node");
+                }
+
+                len += 1;
+                point = link.next;
+            }
+
+            if len != node_ref.len {
+                return Err("node length does not match its points");
+            }
+        }
+
+        if seen.contains(&false) {
+            return Err("point outside of every node");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::points;
+    use crate::{Quadtree, Rect};
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    #[test]
+    fn removal_relinks_the_point_swapped_into_its_slot() {
+        let points = points(20, 12);
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 2, points.clone());
+        let last = tree.points[19];
+        let last_node = tree.links[19].node;
+
+        let first = tree.points[0];
+        tree.remove(first.position, &first.data).unwrap();
+
+        assert_eq!(tree.points[0].data, last.data);
+        assert_eq!(tree.links[0].node, last_node);
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.remove(last.position, &last.data).is_some());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn random_removals_keep_the_arena_consistent() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let mut points = points(1000, 13);
+        let mut tree = Quadtree::from_points(Rect::new(0.0, 0.0, 800.0, 600.0), 3, points.clone());
+        points.shuffle(&mut rng);
+
+        for (removed, point) in points.iter().enumerate() {
+            assert_eq!(
+                tree.remove(point.position, &point.data).unwrap().data,
+                point.data
+            );
+            assert_eq!(tree.len(), 999 - removed);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+
+        assert_eq!(tree.nodes.len() - 1, tree.free.len() * 4);
+    }
+
+    #[test]
+    fn divide_reuses_merged_blocks() {
+        let points = points(500, 14);
+        let mut tree = Quadtree::new(Rect::new(0.0, 0.0, 800.0, 600.0), 4);
+
+        let mut allocated = None;
+        for _ in 0..3 {
+            for point in &points {
+                tree.insert(*point).unwrap();
+            }
+            assert!(tree.free.is_empty());
+            assert_eq!(*allocated.get_or_insert(tree.nodes.len()), tree.nodes.len());
+
+            for point in &points {
+                tree.remove(point.position, &point.data).unwrap();
+            }
+            assert!(tree.root().is_leaf());
+            assert_eq!(tree.free.len() * 4, tree.nodes.len() - 1);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+    }
+}